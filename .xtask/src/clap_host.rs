@@ -0,0 +1,426 @@
+//! Just enough of a CLAP host to run one of our bundled plugins without a DAW.
+
+use anyhow::{bail, Context, Result};
+use clap_sys::{
+    audio_buffer::clap_audio_buffer,
+    entry::clap_plugin_entry,
+    events::*,
+    ext::{
+        audio_ports::{clap_audio_port_info, clap_plugin_audio_ports, CLAP_EXT_AUDIO_PORTS},
+        params::{clap_param_info, clap_plugin_params, CLAP_EXT_PARAMS},
+    },
+    factory::plugin_factory::{clap_plugin_factory, CLAP_PLUGIN_FACTORY_ID},
+    fixedpoint::{CLAP_BEATTIME_FACTOR, CLAP_SECTIME_FACTOR},
+    host::clap_host,
+    id::clap_id,
+    plugin::clap_plugin,
+    process::{clap_process, CLAP_PROCESS_ERROR},
+    version::CLAP_VERSION,
+};
+use libloading::Library;
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    path::{Path, PathBuf},
+    ptr,
+};
+
+pub struct ParamInfo {
+    pub id: clap_id,
+    pub name: String,
+}
+
+/// Musical time handed to the plugin with every block
+pub struct TransportInfo {
+    pub tempo: Option<f64>,
+    pub time_sig: (u16, u16),
+}
+
+/// A loaded, activated and processing plugin instance
+///
+/// Everything is torn down again in the reverse order on drop.
+pub struct ClapInstance {
+    plugin: *const clap_plugin,
+    params: *const clap_plugin_params,
+    sample_rate: f64,
+
+    pub input_channels: Vec<u32>,
+    pub output_channels: Vec<u32>,
+
+    entry: *const clap_plugin_entry,
+    // the host struct must outlive the plugin, and the library must outlive everything
+    _host: Box<clap_host>,
+    _library: Library,
+}
+
+impl ClapInstance {
+    /// Loads the first plugin from a `.clap` bundle and activates it
+    pub fn load(bundle: &Path, sample_rate: f64, max_block_size: u32) -> Result<ClapInstance> {
+        let library_path = library_path(bundle)?;
+
+        unsafe {
+            let library = Library::new(&library_path)
+                .with_context(|| format!("Could not load {}", library_path.display()))?;
+            let entry = *library
+                .get::<*const clap_plugin_entry>(b"clap_entry\0")
+                .context("Library does not export a CLAP entry point")?;
+
+            let bundle_path = CString::new(bundle.to_string_lossy().as_bytes())?;
+            if !(*entry).init.unwrap()(bundle_path.as_ptr()) {
+                bail!("The CLAP entry point failed to initialize");
+            }
+
+            let factory = (*entry).get_factory.unwrap()(CLAP_PLUGIN_FACTORY_ID.as_ptr())
+                as *const clap_plugin_factory;
+            if factory.is_null() || (*factory).get_plugin_count.unwrap()(factory) == 0 {
+                (*entry).deinit.unwrap()();
+                bail!("The bundle does not contain any plugins");
+            }
+
+            let descriptor = (*factory).get_plugin_descriptor.unwrap()(factory, 0);
+
+            let host = Box::new(clap_host {
+                clap_version: CLAP_VERSION,
+                host_data: ptr::null_mut(),
+                name: c"xtask render".as_ptr(),
+                vendor: c"Max Genson".as_ptr(),
+                url: c"https://www.maxgenson.de".as_ptr(),
+                version: c"0.1.0".as_ptr(),
+                get_extension: Some(host_get_extension),
+                request_restart: Some(host_request),
+                request_process: Some(host_request),
+                request_callback: Some(host_request),
+            });
+
+            let plugin = (*factory).create_plugin.unwrap()(factory, &*host, (*descriptor).id);
+            if plugin.is_null() || !(*plugin).init.unwrap()(plugin) {
+                (*entry).deinit.unwrap()();
+                bail!("Could not create an instance of the plugin");
+            }
+
+            let params = (*plugin).get_extension.unwrap()(plugin, CLAP_EXT_PARAMS.as_ptr())
+                as *const clap_plugin_params;
+            let audio_ports =
+                (*plugin).get_extension.unwrap()(plugin, CLAP_EXT_AUDIO_PORTS.as_ptr())
+                    as *const clap_plugin_audio_ports;
+
+            let mut instance = ClapInstance {
+                plugin,
+                params,
+                sample_rate,
+                input_channels: port_channels(plugin, audio_ports, true),
+                output_channels: port_channels(plugin, audio_ports, false),
+                entry,
+                _host: host,
+                _library: library,
+            };
+
+            if !(*plugin).activate.unwrap()(plugin, sample_rate, 1, max_block_size) {
+                // nothing to deactivate yet
+                instance.plugin = ptr::null();
+                (*plugin).destroy.unwrap()(plugin);
+                bail!("The plugin rejected a sample rate of {sample_rate} Hz");
+            }
+            (*plugin).start_processing.unwrap()(plugin);
+
+            Ok(instance)
+        }
+    }
+
+    pub fn name(&self) -> String {
+        unsafe { string_from_ptr((*(*self.plugin).desc).name) }
+    }
+
+    pub fn params(&self) -> Vec<ParamInfo> {
+        if self.params.is_null() {
+            return vec![];
+        }
+
+        unsafe {
+            let count = (*self.params).count.unwrap()(self.plugin);
+
+            (0..count)
+                .filter_map(|index| {
+                    let mut info: clap_param_info = std::mem::zeroed();
+                    (*self.params).get_info.unwrap()(self.plugin, index, &mut info).then(|| {
+                        ParamInfo {
+                            id: info.id,
+                            name: string_from_ptr(info.name.as_ptr()),
+                        }
+                    })
+                })
+                .collect()
+        }
+    }
+
+    /// Parses a value the way the plugin would display it, e.g. `50` for 50 %
+    pub fn text_to_value(&self, param: &ParamInfo, text: &str) -> Result<f64> {
+        let text = CString::new(text)?;
+        let mut value = 0.0;
+
+        let parsed = unsafe {
+            (*self.params).text_to_value.unwrap()(self.plugin, param.id, text.as_ptr(), &mut value)
+        };
+
+        if !parsed {
+            bail!("{:?} is not a valid value for {}", text, param.name);
+        }
+
+        Ok(value)
+    }
+
+    /// Runs one block, every channel slice must be `frames` long
+    pub fn process(
+        &mut self,
+        inputs: &mut [Vec<Vec<f32>>],
+        outputs: &mut [Vec<Vec<f32>>],
+        frames: usize,
+        position: u64,
+        transport: &TransportInfo,
+        events: &[clap_event_param_value],
+    ) -> Result<()> {
+        let mut input_ptrs: Vec<Vec<*mut f32>> = inputs
+            .iter_mut()
+            .map(|port| {
+                port.iter_mut()
+                    .map(|channel| channel.as_mut_ptr())
+                    .collect()
+            })
+            .collect();
+        let mut output_ptrs: Vec<Vec<*mut f32>> = outputs
+            .iter_mut()
+            .map(|port| {
+                port.iter_mut()
+                    .map(|channel| channel.as_mut_ptr())
+                    .collect()
+            })
+            .collect();
+
+        let input_buffers: Vec<clap_audio_buffer> = input_ptrs
+            .iter_mut()
+            .map(|port| audio_buffer(port))
+            .collect();
+        let mut output_buffers: Vec<clap_audio_buffer> = output_ptrs
+            .iter_mut()
+            .map(|port| audio_buffer(port))
+            .collect();
+
+        let in_events = EventList {
+            list: clap_input_events {
+                ctx: ptr::null_mut(),
+                size: Some(input_events_size),
+                get: Some(input_events_get),
+            },
+            len: events.len() as u32,
+            events: events.as_ptr(),
+        };
+        let out_events = clap_output_events {
+            ctx: ptr::null_mut(),
+            try_push: Some(output_events_try_push),
+        };
+
+        let transport_event = transport_event(transport, position, self.sample_rate);
+
+        let process = clap_process {
+            steady_time: position as i64,
+            frames_count: frames as u32,
+            transport: &transport_event,
+            audio_inputs: input_buffers.as_ptr(),
+            audio_outputs: output_buffers.as_mut_ptr(),
+            audio_inputs_count: input_buffers.len() as u32,
+            audio_outputs_count: output_buffers.len() as u32,
+            in_events: &in_events.list,
+            out_events: &out_events,
+        };
+
+        let status = unsafe { (*self.plugin).process.unwrap()(self.plugin, &process) };
+        if status == CLAP_PROCESS_ERROR {
+            bail!("The plugin reported a processing error at sample {position}");
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ClapInstance {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.plugin.is_null() {
+                (*self.plugin).stop_processing.unwrap()(self.plugin);
+                (*self.plugin).deactivate.unwrap()(self.plugin);
+                (*self.plugin).destroy.unwrap()(self.plugin);
+            }
+
+            (*self.entry).deinit.unwrap()();
+        }
+    }
+}
+
+/// Builds a parameter change event at `time` samples into the block
+pub fn param_event(param: &ParamInfo, time: u32, value: f64) -> clap_event_param_value {
+    clap_event_param_value {
+        header: clap_event_header {
+            size: std::mem::size_of::<clap_event_param_value>() as u32,
+            time,
+            space_id: CLAP_CORE_EVENT_SPACE_ID,
+            type_: CLAP_EVENT_PARAM_VALUE,
+            flags: 0,
+        },
+        param_id: param.id,
+        cookie: ptr::null_mut(),
+        note_id: -1,
+        port_index: -1,
+        channel: -1,
+        key: -1,
+        value,
+    }
+}
+
+/// On macOS a bundle is a directory, everywhere else it's the library itself
+fn library_path(bundle: &Path) -> Result<PathBuf> {
+    if bundle.is_file() {
+        return Ok(bundle.to_owned());
+    }
+
+    let name = bundle
+        .file_stem()
+        .with_context(|| format!("{} is not a CLAP bundle", bundle.display()))?;
+    let library = bundle.join("Contents").join("MacOS").join(name);
+
+    if !library.is_file() {
+        bail!("{} is not a CLAP bundle", bundle.display());
+    }
+
+    Ok(library)
+}
+
+unsafe fn port_channels(
+    plugin: *const clap_plugin,
+    audio_ports: *const clap_plugin_audio_ports,
+    is_input: bool,
+) -> Vec<u32> {
+    if audio_ports.is_null() {
+        return vec![];
+    }
+
+    let count = (*audio_ports).count.unwrap()(plugin, is_input);
+
+    (0..count)
+        .filter_map(|index| {
+            let mut info: clap_audio_port_info = std::mem::zeroed();
+            (*audio_ports).get.unwrap()(plugin, index, is_input, &mut info)
+                .then_some(info.channel_count)
+        })
+        .collect()
+}
+
+unsafe fn string_from_ptr(ptr: *const c_char) -> String {
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+fn audio_buffer(channels: &mut [*mut f32]) -> clap_audio_buffer {
+    clap_audio_buffer {
+        data32: channels.as_mut_ptr(),
+        data64: ptr::null_mut(),
+        channel_count: channels.len() as u32,
+        latency: 0,
+        constant_mask: 0,
+    }
+}
+
+fn transport_event(
+    transport: &TransportInfo,
+    position: u64,
+    sample_rate: f64,
+) -> clap_event_transport {
+    let seconds = position as f64 / sample_rate;
+    let mut flags = CLAP_TRANSPORT_IS_PLAYING | CLAP_TRANSPORT_HAS_SECONDS_TIMELINE;
+
+    let (tempo, beats, bar_start, bar_number) = match transport.tempo {
+        Some(tempo) => {
+            flags |= CLAP_TRANSPORT_HAS_TEMPO
+                | CLAP_TRANSPORT_HAS_BEATS_TIMELINE
+                | CLAP_TRANSPORT_HAS_TIME_SIGNATURE;
+
+            let beats = seconds * tempo / 60.0;
+            let beats_per_bar = transport.time_sig.0 as f64 * 4.0 / transport.time_sig.1 as f64;
+            let bar_number = (beats / beats_per_bar).floor();
+
+            (tempo, beats, bar_number * beats_per_bar, bar_number as i32)
+        }
+        None => (0.0, 0.0, 0.0, 0),
+    };
+
+    clap_event_transport {
+        header: clap_event_header {
+            size: std::mem::size_of::<clap_event_transport>() as u32,
+            time: 0,
+            space_id: CLAP_CORE_EVENT_SPACE_ID,
+            type_: CLAP_EVENT_TRANSPORT,
+            flags: 0,
+        },
+        flags,
+        song_pos_beats: (beats * CLAP_BEATTIME_FACTOR as f64).round() as i64,
+        song_pos_seconds: (seconds * CLAP_SECTIME_FACTOR as f64).round() as i64,
+        tempo,
+        tempo_inc: 0.0,
+        loop_start_beats: 0,
+        loop_end_beats: 0,
+        loop_start_seconds: 0,
+        loop_end_seconds: 0,
+        bar_start: (bar_start * CLAP_BEATTIME_FACTOR as f64).round() as i64,
+        bar_number,
+        tsig_num: transport.time_sig.0,
+        tsig_denom: transport.time_sig.1,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Event Lists
+///////////////////////////////////////////////////////////////////////////////
+
+/// `list` has to stay the first field, the callbacks cast the list pointer back to this struct
+#[repr(C)]
+struct EventList {
+    list: clap_input_events,
+    len: u32,
+    events: *const clap_event_param_value,
+}
+
+unsafe extern "C" fn input_events_size(list: *const clap_input_events) -> u32 {
+    (*(list as *const EventList)).len
+}
+
+unsafe extern "C" fn input_events_get(
+    list: *const clap_input_events,
+    index: u32,
+) -> *const clap_event_header {
+    let list = &*(list as *const EventList);
+
+    if index >= list.len {
+        return ptr::null();
+    }
+
+    &(*list.events.add(index as usize)).header
+}
+
+/// Output events like parameter gestures are of no interest when rendering
+unsafe extern "C" fn output_events_try_push(
+    _list: *const clap_output_events,
+    _event: *const clap_event_header,
+) -> bool {
+    true
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Host Callbacks
+///////////////////////////////////////////////////////////////////////////////
+
+unsafe extern "C" fn host_get_extension(
+    _host: *const clap_host,
+    _extension_id: *const c_char,
+) -> *const c_void {
+    ptr::null()
+}
+
+/// Everything happens on a single thread anyways
+unsafe extern "C" fn host_request(_host: *const clap_host) {}
@@ -0,0 +1,294 @@
+//! `cargo xtask golden`, renders every plugin with deterministic stimuli and compares the result
+//! against the WAV files stored in `golden/`.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    f64::consts::TAU,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    clap_host::{ClapInstance, TransportInfo},
+    render::{read_wav, render, write_wav, Automation},
+};
+
+const USAGE: &str =
+    "Usage: cargo xtask golden [--bless] [--tolerance <max deviation>] [packages...]
+
+Bundles the plugins, renders them and compares the renders against golden/.
+Use --bless to store the current renders as the new golden files.";
+
+/// Every plugin that makes a sound, together with the parameters that differ from the defaults
+///
+/// simple_gui is left out on purpose.
+const PLUGINS: &[(&str, &[(&str, &str)])] = &[
+    ("multi_filter", &[]),
+    ("simple_delay", &[]),
+    ("freeverb", &[]),
+    ("stereo_vca", &[("Gate", "On")]),
+    ("simple_oscillator", &[]),
+    ("granu_delay", &[]),
+];
+
+const SAMPLE_RATES: &[u32] = &[44_100, 48_000, 96_000];
+const BLOCK_SIZES: &[usize] = &[64, 512];
+
+/// Length of every render in seconds, the stimuli are followed by silence
+const RENDER_LENGTH: f64 = 0.5;
+const STIMULUS_LENGTH: f64 = 0.25;
+
+/// Absolute deviation per sample, roughly -80 dBFS
+const DEFAULT_TOLERANCE: f32 = 1e-4;
+
+const GOLDEN_DIR: &str = "golden";
+const BUNDLE_DIR: &str = "target/bundled";
+
+#[derive(Clone, Copy)]
+enum Stimulus {
+    Impulse,
+    Sweep,
+    Noise,
+}
+
+impl Stimulus {
+    const ALL: [Stimulus; 3] = [Stimulus::Impulse, Stimulus::Sweep, Stimulus::Noise];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Stimulus::Impulse => "impulse",
+            Stimulus::Sweep => "sweep",
+            Stimulus::Noise => "noise",
+        }
+    }
+
+    /// Generates a stereo signal that is the same on every machine
+    fn generate(&self, sample_rate: u32) -> Vec<Vec<f32>> {
+        let frames = (STIMULUS_LENGTH * sample_rate as f64) as usize;
+
+        match self {
+            Stimulus::Impulse => {
+                let mut impulse = vec![0.0; frames];
+                impulse[0] = 1.0;
+
+                vec![impulse.clone(), impulse]
+            }
+            // logarithmic sine sweep from 20 Hz to 20 kHz or just below nyquist at -6 dBFS
+            Stimulus::Sweep => {
+                let (start, end) = (20.0, 20_000.0_f64.min(sample_rate as f64 * 0.45));
+                let rate = (end / start).ln() / STIMULUS_LENGTH;
+
+                let sweep: Vec<f32> = (0..frames)
+                    .map(|n| {
+                        let t = n as f64 / sample_rate as f64;
+                        let phase = TAU * start * ((rate * t).exp() - 1.0) / rate;
+                        (phase.sin() * 0.5) as f32
+                    })
+                    .collect();
+
+                vec![sweep.clone(), sweep]
+            }
+            // uncorrelated white noise at -12 dBFS peak
+            Stimulus::Noise => [0x1234_5678, 0x9abc_def0]
+                .into_iter()
+                .map(|seed| {
+                    let mut state: u32 = seed;
+                    (0..frames)
+                        .map(|_| {
+                            // xorshift32
+                            state ^= state << 13;
+                            state ^= state >> 17;
+                            state ^= state << 5;
+                            (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * 0.25
+                        })
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BundlerEntry {
+    name: String,
+}
+
+pub fn main(args: &[String]) -> Result<()> {
+    let mut bless = false;
+    let mut tolerance = DEFAULT_TOLERANCE;
+    let mut packages = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            "--tolerance" => {
+                tolerance = args
+                    .next()
+                    .with_context(|| format!("Missing a value for --tolerance\n\n{USAGE}"))?
+                    .parse()?
+            }
+            flag if flag.starts_with('-') => bail!("Unknown option {flag}\n\n{USAGE}"),
+            package => match PLUGINS.iter().find(|(name, _)| *name == package) {
+                Some(plugin) => packages.push(*plugin),
+                None => bail!("{package} is not covered by the golden tests\n\n{USAGE}"),
+            },
+        }
+    }
+
+    if packages.is_empty() {
+        packages = PLUGINS.to_vec();
+    }
+
+    // an empty checkout must not pass, and must not be mistaken for a render that changed
+    if !bless {
+        let missing: Vec<&str> = packages
+            .iter()
+            .map(|(package, _)| *package)
+            .filter(|package| !Path::new(GOLDEN_DIR).join(package).is_dir())
+            .collect();
+
+        if !missing.is_empty() {
+            bail!(
+                "There are no golden files for {} in {GOLDEN_DIR}/, bless them from a known good \
+                 revision with `cargo xtask golden --bless` and commit them",
+                missing.join(", ")
+            );
+        }
+    }
+
+    // always test what's currently in the tree
+    let mut bundle_args = vec![String::from("bundle")];
+    bundle_args.extend(packages.iter().map(|(package, _)| package.to_string()));
+    bundle_args.push(String::from("--release"));
+    nih_plug_xtask::main_with_args("cargo xtask", bundle_args)?;
+
+    let bundle_names: BTreeMap<String, BundlerEntry> =
+        toml::from_str(&std::fs::read_to_string("bundler.toml")?)?;
+
+    let mut failures = vec![];
+    let mut n_cases = 0;
+
+    for (package, params) in packages {
+        let name = &bundle_names
+            .get(package)
+            .with_context(|| format!("{package} is missing in bundler.toml"))?
+            .name;
+        let bundle = Path::new(BUNDLE_DIR).join(format!("{name}.clap"));
+
+        for stimulus in Stimulus::ALL {
+            for &sample_rate in SAMPLE_RATES {
+                let input = stimulus.generate(sample_rate);
+
+                for &block_size in BLOCK_SIZES {
+                    n_cases += 1;
+
+                    let case = format!(
+                        "{package}: {} at {sample_rate} Hz in blocks of {block_size}",
+                        stimulus.name()
+                    );
+                    let golden = golden_path(package, stimulus, sample_rate, block_size);
+
+                    let mut plugin =
+                        ClapInstance::load(&bundle, sample_rate as f64, block_size as u32)?;
+                    let mut lanes = Automation::fixed(params).into_lanes(&plugin, sample_rate)?;
+                    let rendered = render(
+                        &mut plugin,
+                        &input,
+                        (RENDER_LENGTH * sample_rate as f64) as usize,
+                        block_size,
+                        &mut lanes,
+                        &TransportInfo {
+                            tempo: Some(120.0),
+                            time_sig: (4, 4),
+                        },
+                    )?;
+
+                    if bless {
+                        std::fs::create_dir_all(golden.parent().unwrap())?;
+                        write_wav(&golden, &rendered, sample_rate)?;
+                        continue;
+                    }
+
+                    if !golden.exists() {
+                        failures.push(format!("{case}: {} is missing", golden.display()));
+                        continue;
+                    }
+
+                    let (expected, _) = read_wav(&golden)?;
+                    if let Some(mismatch) = compare(&rendered, &expected, tolerance) {
+                        failures.push(format!("{case}: {mismatch}"));
+                    }
+                }
+            }
+        }
+    }
+
+    if bless {
+        println!("Blessed {n_cases} renders in {GOLDEN_DIR}/");
+        return Ok(());
+    }
+
+    for failure in &failures {
+        eprintln!("{failure}");
+    }
+
+    if !failures.is_empty() {
+        bail!(
+            "{} of {n_cases} renders differ from the golden files, run with --bless if that's intended",
+            failures.len()
+        );
+    }
+
+    println!("All {n_cases} renders match the golden files");
+
+    Ok(())
+}
+
+fn golden_path(package: &str, stimulus: Stimulus, sample_rate: u32, block_size: usize) -> PathBuf {
+    Path::new(GOLDEN_DIR).join(package).join(format!(
+        "{}_{sample_rate}_{block_size}.wav",
+        stimulus.name()
+    ))
+}
+
+/// Describes the first difference that's bigger than `tolerance`
+fn compare(rendered: &[Vec<f32>], expected: &[Vec<f32>], tolerance: f32) -> Option<String> {
+    if rendered.len() != expected.len() {
+        return Some(format!(
+            "{} channels instead of {}",
+            rendered.len(),
+            expected.len()
+        ));
+    }
+
+    for (channel, (rendered, expected)) in rendered.iter().zip(expected).enumerate() {
+        if rendered.len() != expected.len() {
+            return Some(format!(
+                "{} samples instead of {}",
+                rendered.len(),
+                expected.len()
+            ));
+        }
+
+        // NaN never compares as bigger, so check it explicitly
+        let deviation =
+            rendered
+                .iter()
+                .zip(expected)
+                .enumerate()
+                .find(|(_, (rendered, expected))| {
+                    let diff = (*rendered - *expected).abs();
+                    diff.is_nan() || diff > tolerance
+                });
+
+        if let Some((frame, (rendered, expected))) = deviation {
+            return Some(format!(
+                "channel {channel} is {rendered} instead of {expected} at sample {frame}"
+            ));
+        }
+    }
+
+    None
+}
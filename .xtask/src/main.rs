@@ -1,3 +1,14 @@
+mod clap_host;
+mod golden;
+mod render;
+
 fn main() -> nih_plug_xtask::Result<()> {
-    nih_plug_xtask::main()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    // everything else is handled by nih-plug's bundler
+    match args.first().map(String::as_str) {
+        Some("golden") => golden::main(&args[1..]),
+        Some("render") => render::main(&args[1..]),
+        _ => nih_plug_xtask::main(),
+    }
 }
@@ -0,0 +1,418 @@
+//! `cargo xtask render`, streams a WAV file through a bundled CLAP plugin.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::clap_host::{param_event, ClapInstance, ParamInfo, TransportInfo};
+
+const USAGE: &str = "Usage: cargo xtask render <plugin.clap> <output.wav> [options]
+
+Options:
+  --input <file.wav>        Audio for the main input, silence if omitted
+  --params <file>           JSON or TOML file with parameter values and ramps
+  --sample-rate <hz>        Defaults to the input's sample rate or 48000
+  --block-size <samples>    Defaults to 512
+  --length <seconds>        Length of the render without an input
+  --tail <seconds>          Silence appended after the input, e.g. for reverb tails
+  --tempo <bpm>             Host tempo, no tempo is reported if omitted
+
+Parameters are addressed by their display name. Values are written the way the plugin
+displays them, e.g. 50 for 50 %, -6 for -6 dB or \"Highpass\" for a choice.";
+
+/// Ramps are resolved into one parameter change every this many samples
+const RAMP_RESOLUTION: usize = 32;
+
+#[derive(Deserialize, Default)]
+pub struct Automation {
+    #[serde(default)]
+    params: Vec<ParamAutomation>,
+}
+
+#[derive(Deserialize)]
+struct ParamAutomation {
+    name: String,
+    /// Set once at the start
+    value: Option<ParamValue>,
+    /// Breakpoints in seconds, linearly interpolated
+    #[serde(default)]
+    ramp: Vec<Breakpoint>,
+}
+
+#[derive(Deserialize)]
+struct Breakpoint {
+    time: f64,
+    value: ParamValue,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ParamValue {
+    Number(f64),
+    Text(String),
+}
+
+impl Automation {
+    /// Sets every parameter once at the start, values are display text as in the automation files
+    pub fn fixed(values: &[(&str, &str)]) -> Automation {
+        Automation {
+            params: values
+                .iter()
+                .map(|(name, value)| ParamAutomation {
+                    name: name.to_string(),
+                    value: Some(ParamValue::Text(value.to_string())),
+                    ramp: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    pub fn into_lanes(self, plugin: &ClapInstance, sample_rate: u32) -> Result<Vec<Lane>> {
+        let mut params = plugin.params();
+
+        self.params
+            .into_iter()
+            .map(|automation| {
+                let index = params
+                    .iter()
+                    .position(|param| param.name == automation.name)
+                    .with_context(|| {
+                        format!("{} has no parameter {:?}", plugin.name(), automation.name)
+                    })?;
+                let param = params.swap_remove(index);
+
+                let mut points = automation
+                    .value
+                    .iter()
+                    .map(|value| Ok((0, plugin.text_to_value(&param, &value.to_text())?)))
+                    .collect::<Result<Vec<_>>>()?;
+
+                for breakpoint in &automation.ramp {
+                    let time = (breakpoint.time.max(0.0) * sample_rate as f64) as u64;
+                    points.push((
+                        time,
+                        plugin.text_to_value(&param, &breakpoint.value.to_text())?,
+                    ));
+                }
+                points.sort_by_key(|(time, _)| *time);
+
+                if points.is_empty() {
+                    bail!("{:?} needs either a value or a ramp", param.name);
+                }
+
+                Ok(Lane {
+                    param,
+                    points,
+                    last_sent: None,
+                })
+            })
+            .collect()
+    }
+}
+
+impl ParamValue {
+    fn to_text(&self) -> String {
+        match self {
+            ParamValue::Number(number) => number.to_string(),
+            ParamValue::Text(text) => text.clone(),
+        }
+    }
+}
+
+/// Automation of a single parameter, converted into the plugin's value space
+pub struct Lane {
+    param: ParamInfo,
+    /// (sample position, value)
+    points: Vec<(u64, f64)>,
+    last_sent: Option<f64>,
+}
+
+impl Lane {
+    fn value_at(&self, position: u64) -> f64 {
+        let next = self.points.partition_point(|(time, _)| *time <= position);
+
+        match (
+            next.checked_sub(1).map(|i| self.points[i]),
+            self.points.get(next),
+        ) {
+            (Some((t0, v0)), Some(&(t1, v1))) => {
+                v0 + (v1 - v0) * (position - t0) as f64 / (t1 - t0) as f64
+            }
+            (Some((_, v0)), None) => v0,
+            (None, Some(&(_, v1))) => v1,
+            (None, None) => unreachable!("lanes always have at least one point"),
+        }
+    }
+}
+
+struct Options {
+    plugin: PathBuf,
+    output: PathBuf,
+    input: Option<PathBuf>,
+    params: Option<PathBuf>,
+    sample_rate: Option<u32>,
+    block_size: usize,
+    length: Option<f64>,
+    tail: f64,
+    tempo: Option<f64>,
+}
+
+pub fn main(args: &[String]) -> Result<()> {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => bail!("{err}\n\n{USAGE}"),
+    };
+
+    let input = options.input.as_deref().map(read_wav).transpose()?;
+    let sample_rate = match (&input, options.sample_rate) {
+        (Some((_, input_rate)), Some(rate)) if *input_rate != rate => {
+            bail!("The input has a sample rate of {input_rate} Hz, resample it to {rate} Hz first")
+        }
+        (Some((_, input_rate)), _) => *input_rate,
+        (None, rate) => rate.unwrap_or(48_000),
+    };
+
+    let input_frames = match (&input, options.length) {
+        (Some((channels, _)), _) => channels[0].len(),
+        (None, Some(length)) => (length * sample_rate as f64) as usize,
+        (None, None) => bail!("Either an input or a --length is needed\n\n{USAGE}"),
+    };
+    let total_frames = input_frames + (options.tail * sample_rate as f64) as usize;
+
+    let mut plugin = ClapInstance::load(
+        &options.plugin,
+        sample_rate as f64,
+        options.block_size as u32,
+    )?;
+    let mut lanes = match &options.params {
+        Some(path) => read_automation(path)?.into_lanes(&plugin, sample_rate)?,
+        None => vec![],
+    };
+    let transport = TransportInfo {
+        tempo: options.tempo,
+        time_sig: (4, 4),
+    };
+
+    let main_input = input.map(|(channels, _)| channels).unwrap_or_default();
+    if let Some(&plugin_channels) = plugin.input_channels.first() {
+        if main_input.len() > plugin_channels as usize {
+            bail!(
+                "The input has {} channels, but {} only takes {plugin_channels}",
+                main_input.len(),
+                plugin.name()
+            );
+        }
+    }
+
+    let rendered = render(
+        &mut plugin,
+        &main_input,
+        total_frames,
+        options.block_size,
+        &mut lanes,
+        &transport,
+    )?;
+
+    write_wav(&options.output, &rendered, sample_rate)?;
+
+    println!(
+        "Rendered {:.2} s of {} to {}",
+        total_frames as f64 / sample_rate as f64,
+        plugin.name(),
+        options.output.display()
+    );
+
+    Ok(())
+}
+
+/// Streams `main_input` through the plugin and returns the main output
+///
+/// The input is padded with silence up to `total_frames`, mono gets copied to all channels.
+pub fn render(
+    plugin: &mut ClapInstance,
+    main_input: &[Vec<f32>],
+    total_frames: usize,
+    block_size: usize,
+    lanes: &mut [Lane],
+    transport: &TransportInfo,
+) -> Result<Vec<Vec<f32>>> {
+    let mut inputs = port_buffers(&plugin.input_channels, block_size);
+    let mut outputs = port_buffers(&plugin.output_channels, block_size);
+    let mut rendered = vec![Vec::with_capacity(total_frames); outputs.first().map_or(0, Vec::len)];
+
+    let mut position = 0;
+    while position < total_frames {
+        let frames = block_size.min(total_frames - position);
+
+        // fill the main input and silence everything else
+        for (port_idx, port) in inputs.iter_mut().enumerate() {
+            for (channel_idx, channel) in port.iter_mut().enumerate() {
+                for (i, sample) in channel[..frames].iter_mut().enumerate() {
+                    *sample = match main_input.get(channel_idx).or(main_input.first()) {
+                        Some(source) if port_idx == 0 => {
+                            source.get(position + i).copied().unwrap_or(0.0)
+                        }
+                        _ => 0.0,
+                    };
+                }
+            }
+        }
+
+        let events = block_events(lanes, position as u64, frames);
+
+        plugin.process(
+            &mut inputs,
+            &mut outputs,
+            frames,
+            position as u64,
+            transport,
+            &events,
+        )?;
+
+        if let Some(main_output) = outputs.first() {
+            for (rendered, channel) in rendered.iter_mut().zip(main_output) {
+                rendered.extend_from_slice(&channel[..frames]);
+            }
+        }
+
+        position += frames;
+    }
+
+    Ok(rendered)
+}
+
+fn parse_args(args: &[String]) -> Result<Options> {
+    let mut args = args.iter();
+
+    let mut options = Options {
+        plugin: args.next().context("Missing the plugin bundle")?.into(),
+        output: args.next().context("Missing the output file")?.into(),
+        input: None,
+        params: None,
+        sample_rate: None,
+        block_size: 512,
+        length: None,
+        tail: 0.0,
+        tempo: None,
+    };
+
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .with_context(|| format!("Missing a value for {flag}"))?;
+
+        match flag.as_str() {
+            "--input" => options.input = Some(value.into()),
+            "--params" => options.params = Some(value.into()),
+            "--sample-rate" => options.sample_rate = Some(value.parse()?),
+            "--block-size" => options.block_size = value.parse()?,
+            "--length" => options.length = Some(value.parse()?),
+            "--tail" => options.tail = value.parse()?,
+            "--tempo" => options.tempo = Some(value.parse()?),
+            _ => bail!("Unknown option {flag}"),
+        }
+    }
+
+    if options.block_size == 0 {
+        bail!("The block size must be at least one sample");
+    }
+
+    Ok(options)
+}
+
+fn read_automation(path: &Path) -> Result<Automation> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Could not read {}", path.display()))?;
+
+    let automation = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&contents)?,
+        _ => serde_json::from_str(&contents)?,
+    };
+
+    Ok(automation)
+}
+
+/// Samples every lane within the block, only sending changed values
+fn block_events(
+    lanes: &mut [Lane],
+    position: u64,
+    frames: usize,
+) -> Vec<clap_sys::events::clap_event_param_value> {
+    let mut events = vec![];
+
+    for lane in lanes.iter_mut() {
+        for offset in (0..frames).step_by(RAMP_RESOLUTION) {
+            let value = lane.value_at(position + offset as u64);
+
+            if lane.last_sent != Some(value) {
+                events.push(param_event(&lane.param, offset as u32, value));
+                lane.last_sent = Some(value);
+            }
+        }
+    }
+
+    // the plugin expects the events in order
+    events.sort_by_key(|event| event.header.time);
+
+    events
+}
+
+fn port_buffers(channels: &[u32], block_size: usize) -> Vec<Vec<Vec<f32>>> {
+    channels
+        .iter()
+        .map(|&channels| vec![vec![0.0; block_size]; channels as usize])
+        .collect()
+}
+
+/// Returns the deinterleaved channels and the sample rate
+pub fn read_wav(path: &Path) -> Result<(Vec<Vec<f32>>, u32)> {
+    let mut reader = hound::WavReader::open(path)
+        .with_context(|| format!("Could not open {}", path.display()))?;
+    let spec = reader.spec();
+
+    let interleaved = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 * scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let channels = (0..spec.channels as usize)
+        .map(|channel| {
+            interleaved
+                .iter()
+                .skip(channel)
+                .step_by(spec.channels as usize)
+                .copied()
+                .collect()
+        })
+        .collect();
+
+    Ok((channels, spec.sample_rate))
+}
+
+pub fn write_wav(path: &Path, channels: &[Vec<f32>], sample_rate: u32) -> Result<()> {
+    let spec = hound::WavSpec {
+        channels: channels.len() as u16,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+
+    let mut writer = hound::WavWriter::create(path, spec)
+        .with_context(|| format!("Could not create {}", path.display()))?;
+
+    for frame in 0..channels.first().map_or(0, Vec::len) {
+        for channel in channels {
+            writer.write_sample(channel[frame])?;
+        }
+    }
+
+    writer.finalize()?;
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+//! A feedback delay network of eight lines, mixed by a Hadamard matrix, behind a chain of
+//! diffusing allpasses. Every line has its own decay filter, so all of them fade out together.
+
+use crate::tank::{Decay, DecayFilter, Diffuser};
+use plugin_common::{
+    delay_line::DelayLine,
+    lfo::{Lfo, LfoShape},
+};
+
+const NUM_LINES: usize = 8;
+
+/// In samples at 44.1 kHz, primes between 46 ms and 98 ms
+const LINE_TUNING: [usize; NUM_LINES] = [2053, 2371, 2683, 2999, 3307, 3659, 3989, 4337];
+const DIFFUSER_TUNING: [usize; 4] = [556, 441, 341, 225];
+const TUNING_SAMPLE_RATE: f32 = 44_100.0;
+const DIFFUSION: f32 = 0.6;
+
+/// Every line gets the input with its own sign, so they don't start out in phase
+const INPUT_SIGNS: [f32; NUM_LINES] = [1.0, -1.0, 1.0, 1.0, -1.0, 1.0, -1.0, -1.0];
+
+/// Brings the hall to about the loudness of the comb bank
+const INPUT_GAIN: f32 = 9.0;
+
+fn adjust_length(length: usize, sr: f32) -> usize {
+    ((length as f32 * sr / TUNING_SAMPLE_RATE).round() as usize).max(2)
+}
+
+/// Mixes all lines into each other without changing the overall energy
+#[inline(always)]
+fn hadamard(lines: &mut [f32; NUM_LINES]) {
+    let mut size = 1;
+    while size < NUM_LINES {
+        for start in (0..NUM_LINES).step_by(size * 2) {
+            for i in start..start + size {
+                let (a, b) = (lines[i], lines[i + size]);
+                lines[i] = a + b;
+                lines[i + size] = a - b;
+            }
+        }
+        size *= 2;
+    }
+
+    let scale = 1.0 / (NUM_LINES as f32).sqrt();
+    lines.iter_mut().for_each(|line| *line *= scale);
+}
+
+pub struct Hall {
+    diffusers: [Diffuser; 4],
+    lines: [(DelayLine, f32); NUM_LINES],
+    damping: [DecayFilter; NUM_LINES],
+    sr: f32,
+    decay: Decay,
+}
+
+impl Hall {
+    /// `headroom` is the deepest modulation in samples
+    pub fn new(sr: f32, headroom: usize) -> Hall {
+        Hall {
+            diffusers: DIFFUSER_TUNING
+                .map(|length| Diffuser::new(adjust_length(length, sr), 0, DIFFUSION)),
+            lines: LINE_TUNING.map(|length| {
+                let length = adjust_length(length, sr);
+                (DelayLine::new(length + headroom + 2), length as f32)
+            }),
+            damping: std::array::from_fn(|_| DecayFilter::new(sr)),
+            sr,
+            decay: Decay::default(),
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    pub fn set_decay(&mut self, decay: Decay) {
+        if decay == self.decay {
+            return;
+        }
+        self.decay = decay;
+
+        for ((_, length), damping) in self.lines.iter().zip(self.damping.iter_mut()) {
+            damping.set_gains(decay.gains(length / self.sr));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.diffusers.iter_mut().for_each(Diffuser::clear);
+        self.lines.iter_mut().for_each(|(line, _)| line.clear());
+        self.damping.iter_mut().for_each(DecayFilter::clear);
+    }
+
+    /// `depth` is the modulation of the line lengths in samples
+    #[inline(always)]
+    pub fn tick(&mut self, input: f32, lfo: &Lfo, depth: f32) -> (f32, f32) {
+        let diffused = self
+            .diffusers
+            .iter_mut()
+            .fold(input * INPUT_GAIN, |sample, diffuser| {
+                diffuser.tick(sample, 0.0)
+            });
+
+        let mut outputs = [0.0; NUM_LINES];
+        for (i, (line, length)) in self.lines.iter().enumerate() {
+            let offset = lfo.value(LfoShape::Sine, i as f32 / NUM_LINES as f32) * depth;
+            outputs[i] = line.read(length + offset);
+        }
+
+        let mut feedback = outputs;
+        for (sample, damping) in feedback.iter_mut().zip(self.damping.iter_mut()) {
+            *sample = damping.tick(*sample);
+        }
+        hadamard(&mut feedback);
+
+        for (i, (line, _)) in self.lines.iter_mut().enumerate() {
+            line.write(diffused * INPUT_SIGNS[i] + feedback[i]);
+        }
+
+        // even lines go left and odd lines go right, with alternating signs
+        outputs
+            .chunks_exact(4)
+            .fold((0.0, 0.0), |(left, right), chunk| {
+                (left + chunk[0] - chunk[2], right + chunk[1] - chunk[3])
+            })
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tank::tests::decay_time;
+
+    #[test]
+    fn hadamard_keeps_the_energy() {
+        let mut lines = [1.0, 0.5, -0.25, 0.0, 2.0, -1.0, 0.1, 0.3];
+        let energy = |lines: &[f32]| lines.iter().map(|x| x * x).sum::<f32>();
+
+        let before = energy(&lines);
+        hadamard(&mut lines);
+
+        assert!((energy(&lines) - before).abs() < 1e-5);
+    }
+
+    #[test]
+    fn tail_follows_the_decay_time() {
+        for (sr, rt60) in [(44_100.0, 1.0), (48_000.0, 2.0), (96_000.0, 1.0)] {
+            let mut hall = Hall::new(sr, 0);
+            hall.set_decay(Decay {
+                time: rt60,
+                low: 1.0,
+                high: 1.0,
+                freeze: 0.0,
+            });
+
+            let lfo = Lfo::new(1);
+            let measured = decay_time(sr, |input| hall.tick(input, &lfo, 0.0));
+
+            assert!(
+                (measured / rt60 - 1.0).abs() < 0.2,
+                "{measured} s at {sr} Hz"
+            );
+        }
+    }
+}
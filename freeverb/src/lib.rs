@@ -1,34 +1,185 @@
-use nih_plug::prelude::*;
-use std::sync::Arc;
+use nih_plug::{prelude::*, util};
+use plugin_common::{
+    check_sample_rate,
+    filter::{HIGHEST_CUT, LOWEST_CUT},
+    param::{gain_db, hz, percentage, seconds},
+    plugin_info, sidechain_sample, stereo_frame,
+    tempo::NoteDivision,
+    transient::TransientDetector,
+    STEREO_SIDECHAIN_IO_LAYOUTS,
+};
+use std::{f32::consts::FRAC_PI_2, ops::RangeInclusive, sync::Arc};
+
+mod hall;
+mod plate;
+mod reverb;
+mod tank;
+
+use reverb::{Algorithm, Reverb, MAX_PRE_DELAY};
+
+/// Below 8 kHz the shortest allpass would only be a handful of samples long, above 384 kHz the
+/// delay lines get unreasonably large
+const SUPPORTED_SAMPLE_RATES: RangeInclusive<f32> = 8_000.0..=384_000.0;
+
+const SMOOTHING_TIME_MS: f32 = 20.0;
+
+/// Default time it takes to freeze or release the tail
+const FREEZE_FADE_MS: f32 = 50.0;
+
+/// Time it takes to move to a new pre-delay after the tempo or the division changed
+const PRE_DELAY_GLIDE_MS: f32 = 100.0;
+
+#[derive(Enum, PartialEq)]
+enum MixLaw {
+    Linear,
+    /// Keeps the loudness in the middle of the range
+    #[name = "Equal Power"]
+    EqualPower,
+}
+
+#[derive(Enum, PartialEq)]
+enum FreezeLatch {
+    Off,
+    /// Every note on toggles the freeze
+    #[name = "MIDI"]
+    Midi,
+    /// Every transient in the sidechain toggles the freeze
+    Sidechain,
+}
+
+impl MixLaw {
+    /// Returns the normalized (dry, wet) levels for `mix`
+    fn blend(&self, mix: f32) -> (f32, f32) {
+        match self {
+            MixLaw::Linear => (1.0 - mix, mix),
+            MixLaw::EqualPower => {
+                let angle = mix * FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+}
 
 struct Freeverb {
     params: Arc<FreeverbParams>,
-    freeverb: yanel_dsp::Freeverb,
+    freeverb: Reverb,
+    /// From 0.0 to 1.0, so freezing fades instead of switching hard
+    freeze: Smoother<f32>,
+    frozen: bool,
+    /// Toggled by the latch triggers, freezes the tail even if the switch is off
+    latched: bool,
+    transients: TransientDetector,
+    /// Pre-delay in seconds, either free or synced
+    pre_delay: Smoother<f32>,
+    pre_delay_target: f32,
+    /// From 0.0 to 1.0, so switching the modulation on and off doesn't click
+    modulation: Smoother<f32>,
+    modulated: bool,
+    sr: f32,
 }
 
 #[derive(Params)]
 struct FreeverbParams {
+    #[id = "Algorithm"]
+    pub algorithm: EnumParam<Algorithm>,
+
+    /// Freeverb only
     #[id = "Damp"]
     pub damp: FloatParam,
 
+    /// Freeverb only
     #[id = "Room Size"]
     pub room_size: FloatParam,
 
+    /// Plate and hall only, time until the tail fell by 60 dB
+    #[id = "Decay"]
+    pub decay: FloatParam,
+
+    /// Plate and hall only, multiplies the decay time of the lows
+    #[id = "Low Decay"]
+    pub low_decay: FloatParam,
+
+    /// Plate and hall only, multiplies the decay time of the highs
+    #[id = "High Decay"]
+    pub high_decay: FloatParam,
+
     #[id = "Width"]
     pub width: FloatParam,
 
     #[id = "mix"]
     pub mix: FloatParam,
 
+    #[id = "Mix Law"]
+    pub mix_law: EnumParam<MixLaw>,
+
+    #[id = "Dry Level"]
+    pub dry_level: FloatParam,
+
+    #[id = "Wet Level"]
+    pub wet_level: FloatParam,
+
+    /// For using the reverb on an aux send
+    #[id = "Wet Only"]
+    pub wet_only: BoolParam,
+
     #[id = "Freeze"]
     pub freeze: BoolParam,
+
+    #[id = "Freeze Fade"]
+    pub freeze_fade: FloatParam,
+
+    #[id = "Freeze Latch"]
+    pub freeze_latch: EnumParam<FreezeLatch>,
+
+    /// How far a sidechain transient has to stick out to toggle the latch
+    #[id = "Latch Threshold"]
+    pub latch_threshold: FloatParam,
+
+    #[id = "Pre-Delay"]
+    pub pre_delay: FloatParam,
+
+    #[id = "Pre-Delay Sync"]
+    pub pre_delay_sync: BoolParam,
+
+    #[id = "Pre-Delay Division"]
+    pub pre_delay_division: EnumParam<NoteDivision>,
+
+    #[id = "Input Low Cut"]
+    pub input_low_cut: FloatParam,
+
+    #[id = "Input High Cut"]
+    pub input_high_cut: FloatParam,
+
+    #[id = "Wet Low Cut"]
+    pub wet_low_cut: FloatParam,
+
+    #[id = "Wet High Cut"]
+    pub wet_high_cut: FloatParam,
+
+    #[id = "Modulation"]
+    pub modulation: BoolParam,
+
+    #[id = "Mod Rate"]
+    pub mod_rate: FloatParam,
+
+    #[id = "Mod Depth"]
+    pub mod_depth: FloatParam,
 }
 
 impl Default for Freeverb {
     fn default() -> Self {
         Self {
             params: Arc::new(FreeverbParams::default()),
-            freeverb: yanel_dsp::Freeverb::new(48_000),
+            freeverb: Reverb::new(48_000.0),
+            freeze: Smoother::new(SmoothingStyle::Linear(FREEZE_FADE_MS)),
+            frozen: false,
+            latched: false,
+            transients: TransientDetector::new(48_000.0),
+            pre_delay: Smoother::new(SmoothingStyle::Linear(PRE_DELAY_GLIDE_MS)),
+            pre_delay_target: 0.0,
+            modulation: Smoother::new(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+            modulated: false,
+            sr: 48_000.0,
         }
     }
 }
@@ -36,42 +187,138 @@ impl Default for Freeverb {
 impl Default for FreeverbParams {
     fn default() -> Self {
         Self {
-            damp: FloatParam::new("Damp", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_value_to_string(formatters::v2s_f32_percentage(0))
-                .with_unit(" %"),
-            room_size: FloatParam::new("Room Size", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_value_to_string(formatters::v2s_f32_percentage(0))
-                .with_unit(" %"),
+            algorithm: EnumParam::new("Algorithm", Algorithm::Freeverb),
+
+            damp: percentage("Damp", 0.5, 0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+            room_size: percentage("Room Size", 0.5, 0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+
+            decay: seconds(
+                "Decay",
+                2.0,
+                FloatRange::Skewed {
+                    min: 0.2,
+                    max: 30.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
 
-            width: FloatParam::new("Width", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_value_to_string(formatters::v2s_f32_percentage(0))
-                .with_unit(" %"),
+            low_decay: decay_multiplier("Low Decay", 1.2),
 
-            mix: FloatParam::new("Mix", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_value_to_string(formatters::v2s_f32_percentage(0))
-                .with_unit(" %"),
+            high_decay: decay_multiplier("High Decay", 0.5),
 
-            freeze: BoolParam::new("Freeze", false)
-                .with_value_to_string(formatters::v2s_bool_bypass()),
+            width: percentage("Width", 0.5, 0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+
+            mix: percentage("Mix", 0.5, 0).with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+
+            mix_law: EnumParam::new("Mix Law", MixLaw::Linear),
+
+            dry_level: gain_db("Dry Level", 0.0, util::MINUS_INFINITY_DB, 6.0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+
+            wet_level: gain_db("Wet Level", 0.0, util::MINUS_INFINITY_DB, 6.0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+
+            wet_only: BoolParam::new("Wet Only", false),
+
+            freeze: BoolParam::new("Freeze", false),
+
+            freeze_fade: FloatParam::new(
+                "Freeze Fade",
+                FREEZE_FADE_MS,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 5_000.0,
+                    factor: 0.3,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            freeze_latch: EnumParam::new("Freeze Latch", FreezeLatch::Off),
+
+            latch_threshold: FloatParam::new(
+                "Latch Threshold",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            pre_delay: seconds(
+                "Pre-Delay",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: MAX_PRE_DELAY,
+                    factor: 0.5,
+                },
+            ),
+
+            pre_delay_sync: BoolParam::new("Pre-Delay Sync", false),
+
+            pre_delay_division: EnumParam::new("Pre-Delay Division", NoteDivision::Sixteenth),
+
+            input_low_cut: hz("Input Low Cut", LOWEST_CUT, LOWEST_CUT, 2_000.0),
+
+            input_high_cut: hz("Input High Cut", HIGHEST_CUT, 500.0, HIGHEST_CUT),
+
+            wet_low_cut: hz("Wet Low Cut", LOWEST_CUT, LOWEST_CUT, 2_000.0),
+
+            wet_high_cut: hz("Wet High Cut", HIGHEST_CUT, 500.0, HIGHEST_CUT),
+
+            modulation: BoolParam::new("Modulation", false),
+
+            mod_rate: hz("Mod Rate", 0.5, 0.05, 5.0),
+
+            mod_depth: percentage("Mod Depth", 0.5, 0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
         }
     }
 }
 
-impl Plugin for Freeverb {
-    const NAME: &'static str = "Freeverb";
-    const VENDOR: &'static str = "Max Genson";
-    const URL: &'static str = "https://www.maxgenson.de";
-    const EMAIL: &'static str = "mail@maxgenson.de";
+/// Multiplies the decay time of one band
+fn decay_multiplier(name: &str, default: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed {
+            min: 0.1,
+            max: 4.0,
+            factor: FloatRange::skew_factor(-1.5),
+        },
+    )
+    .with_unit(" x")
+    .with_value_to_string(formatters::v2s_f32_rounded(2))
+    .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS))
+}
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+impl FreeverbParams {
+    /// Falls back to the free time if the host doesn't report a tempo
+    fn pre_delay(&self, tempo: Option<f64>) -> f32 {
+        match tempo {
+            Some(tempo) if self.pre_delay_sync.value() && tempo > 0.0 => self
+                .pre_delay_division
+                .value()
+                .seconds(tempo)
+                .min(MAX_PRE_DELAY),
+            _ => self.pre_delay.value(),
+        }
+    }
+}
+
+impl Plugin for Freeverb {
+    plugin_info!("Freeverb");
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
-        ..AudioIOLayout::const_default()
-    }];
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = STEREO_SIDECHAIN_IO_LAYOUTS;
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
     type SysExMessage = ();
@@ -84,11 +331,14 @@ impl Plugin for Freeverb {
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
+        buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
-        // Do this only once
-        self.freeverb.check_buffer_alignment();
+        if !check_sample_rate(buffer_config.sample_rate, SUPPORTED_SAMPLE_RATES) {
+            return false;
+        }
+
+        self.set_sample_rate(buffer_config.sample_rate);
 
         true
     }
@@ -96,21 +346,24 @@ impl Plugin for Freeverb {
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        self.freeverb.set_all(
-            self.params.damp.smoothed.next(),
-            self.params.room_size.smoothed.next(),
-            self.params.width.smoothed.next(),
-            self.params.freeze.value(),
-            self.params.mix.smoothed.next(),
-        );
+        self.update(context.transport().tempo);
+
+        let latch = self.params.freeze_latch.value();
+        let mut next_event = context.next_event();
+
+        for (n, channel_samples) in buffer.iter_samples().enumerate() {
+            self.handle_events(n, &mut next_event, || context.next_event());
 
-        for channel_samples in buffer.iter_samples() {
-            let mut samples = channel_samples.into_iter();
-            let (left, right) = (samples.next().unwrap(), samples.next().unwrap());
-            (*left, *right) = self.freeverb.tick((*left, *right));
+            // keeps listening while the latch is off, so switching to it doesn't fire right away
+            if self.transients.tick(sidechain_sample(aux, n)) && latch == FreezeLatch::Sidechain {
+                self.toggle_latch();
+            }
+
+            let (left, right) = stereo_frame(channel_samples);
+            (*left, *right) = self.tick((*left, *right));
         }
 
         ProcessStatus::Normal
@@ -119,6 +372,160 @@ impl Plugin for Freeverb {
     fn deactivate(&mut self) {}
 }
 
+impl Freeverb {
+    /// All delay lengths are scaled to the sample rate, so the delay lines need to be rebuilt
+    fn set_sample_rate(&mut self, sr: f32) {
+        self.sr = sr;
+        self.freeverb = Reverb::new(sr);
+
+        self.latched = false;
+        self.frozen = self.params.freeze.value();
+        self.freeze.reset(if self.frozen { 1.0 } else { 0.0 });
+        self.transients = TransientDetector::new(sr);
+
+        // without a transport the synced time is unknown, so start from the free time
+        self.pre_delay_target = self.params.pre_delay.value();
+        self.pre_delay.reset(self.pre_delay_target);
+
+        self.modulated = self.params.modulation.value();
+        self.modulation
+            .reset(if self.modulated { 1.0 } else { 0.0 });
+    }
+
+    /// Applies everything that only changes once per block
+    ///
+    /// The blocks are split at every parameter change, so this still follows the automation.
+    fn update(&mut self, tempo: Option<f64>) {
+        self.freeverb.set_algorithm(self.params.algorithm.value());
+        self.freeverb.set_decay(
+            self.params.decay.smoothed.previous_value(),
+            self.params.low_decay.smoothed.previous_value(),
+            self.params.high_decay.smoothed.previous_value(),
+        );
+
+        if self.params.freeze_latch.value() == FreezeLatch::Off {
+            self.latched = false;
+        }
+        self.update_freeze();
+        self.transients
+            .set_threshold(self.params.latch_threshold.value());
+
+        let pre_delay = self.params.pre_delay(tempo);
+        if pre_delay != self.pre_delay_target {
+            self.pre_delay_target = pre_delay;
+            self.pre_delay.set_target(self.sr, pre_delay);
+        }
+
+        if self.params.modulation.value() != self.modulated {
+            self.modulated = self.params.modulation.value();
+            self.modulation
+                .set_target(self.sr, if self.modulated { 1.0 } else { 0.0 });
+        }
+
+        self.freeverb.set_input_filter(
+            self.params.input_low_cut.value(),
+            self.params.input_high_cut.value(),
+        );
+        self.freeverb.set_tone(
+            self.params.wet_low_cut.value(),
+            self.params.wet_high_cut.value(),
+        );
+    }
+
+    /// Starts fading towards the new freeze state, if it changed
+    fn update_freeze(&mut self) {
+        let frozen = self.params.freeze.value() || self.latched;
+
+        // restarting the fades every block would make them depend on the block size
+        if frozen != self.frozen {
+            self.frozen = frozen;
+            self.freeze.style = SmoothingStyle::Linear(self.params.freeze_fade.value());
+            self.freeze
+                .set_target(self.sr, if self.frozen { 1.0 } else { 0.0 });
+        }
+    }
+
+    /// Handles all events up to sample `n`, `next_event` is the first one that hasn't been
+    /// handled yet
+    fn handle_events(
+        &mut self,
+        n: usize,
+        next_event: &mut Option<NoteEvent<()>>,
+        mut next: impl FnMut() -> Option<NoteEvent<()>>,
+    ) {
+        while let Some(event) = *next_event {
+            if event.timing() > n as u32 {
+                break;
+            }
+
+            if matches!(event, NoteEvent::NoteOn { .. })
+                && self.params.freeze_latch.value() == FreezeLatch::Midi
+            {
+                self.toggle_latch();
+            }
+
+            *next_event = next();
+        }
+    }
+
+    fn toggle_latch(&mut self) {
+        self.latched = !self.latched;
+        self.update_freeze();
+    }
+
+    /// Updates the reverb with the smoothed parameters and processes one stereo frame
+    fn tick(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let params = &self.params;
+
+        let mix = params.mix.smoothed.next();
+        let (dry, wet) = if params.wet_only.value() {
+            (0.0, 1.0)
+        } else {
+            params.mix_law.value().blend(mix)
+        };
+
+        self.freeverb.set_all(
+            params.damp.smoothed.next(),
+            params.room_size.smoothed.next(),
+            params.width.smoothed.next(),
+            self.freeze.next(),
+            wet * params.wet_level.smoothed.next(),
+            dry * params.dry_level.smoothed.next(),
+        );
+        // update() catches the jumps, so the decay gains are only recalculated while it moves
+        if params.decay.smoothed.is_smoothing()
+            || params.low_decay.smoothed.is_smoothing()
+            || params.high_decay.smoothed.is_smoothing()
+        {
+            self.freeverb.set_decay(
+                params.decay.smoothed.next(),
+                params.low_decay.smoothed.next(),
+                params.high_decay.smoothed.next(),
+            );
+        }
+        self.freeverb.set_pre_delay(self.pre_delay.next());
+        // once faded out, the depth is exactly zero and the combs are back to their fixed lengths
+        self.freeverb.set_modulation(
+            params.mod_rate.value(),
+            params.mod_depth.smoothed.next() * self.modulation.next(),
+        );
+
+        self.freeverb.tick(input)
+    }
+}
+
+impl ClapPlugin for Freeverb {
+    const CLAP_ID: &'static str = "de.maxgenson.freeverb";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("An implementation of the famous Freeverb");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Reverb,
+    ];
+}
+
 impl Vst3Plugin for Freeverb {
     const VST3_CLASS_ID: [u8; 16] = *b"FreeverbMG......";
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
@@ -128,4 +535,122 @@ impl Vst3Plugin for Freeverb {
     ];
 }
 
+nih_export_clap!(Freeverb);
 nih_export_vst3!(Freeverb);
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    /// Every note on toggles the freeze
+    fn midi_latched() -> Freeverb {
+        let params = FreeverbParams {
+            freeze_latch: EnumParam::new("Freeze Latch", FreezeLatch::Midi),
+            ..Default::default()
+        };
+        let mut freeverb = Freeverb {
+            params: Arc::new(params),
+            ..Default::default()
+        };
+        freeverb.set_sample_rate(SR);
+
+        freeverb
+    }
+
+    fn note_on(timing: u32) -> NoteEvent<()> {
+        NoteEvent::NoteOn {
+            timing,
+            voice_id: None,
+            channel: 0,
+            note: 60,
+            velocity: 1.0,
+        }
+    }
+
+    /// Renders an impulse in blocks the way `process` does, while room size, damp and mix are
+    /// ramping and a note on freezes the tail halfway through
+    fn render(block_size: usize) -> Vec<(f32, f32)> {
+        let mut freeverb = midi_latched();
+
+        let params = &freeverb.params;
+        for param in [
+            &params.damp,
+            &params.room_size,
+            &params.decay,
+            &params.low_decay,
+            &params.high_decay,
+            &params.width,
+            &params.mix,
+            &params.dry_level,
+            &params.wet_level,
+            &params.mod_depth,
+        ] {
+            param.smoothed.reset(param.value());
+        }
+        params.room_size.smoothed.set_target(SR, 0.9);
+        params.damp.smoothed.set_target(SR, 0.1);
+        params.mix.smoothed.set_target(SR, 1.0);
+
+        let mut output = Vec::new();
+
+        for block in 0..SR as usize / block_size {
+            freeverb.update(None);
+
+            // the note lands in whichever block it falls into, at the same sample for every size
+            let start = block * block_size;
+            let mut events = (start..start + block_size)
+                .filter(|&n| n == SR as usize / 2 + 7)
+                .map(|n| note_on((n - start) as u32));
+            let mut next_event = events.next();
+
+            for n in 0..block_size {
+                freeverb.handle_events(n, &mut next_event, || events.next());
+                let impulse = if block == 0 && n == 0 { 1.0 } else { 0.0 };
+                output.push(freeverb.tick((impulse, impulse)));
+            }
+        }
+
+        output
+    }
+
+    #[test]
+    fn output_does_not_depend_on_block_size() {
+        let reference = render(1);
+
+        for block_size in [32, 64, 500, 1_000] {
+            assert_eq!(render(block_size), reference, "with blocks of {block_size}");
+        }
+    }
+
+    #[test]
+    fn latch_toggles_the_freeze() {
+        let mut freeverb = midi_latched();
+        let fade = (FREEZE_FADE_MS / 1000.0 * SR) as usize;
+
+        for frozen in [1.0, 0.0] {
+            let mut next_event = Some(note_on(0));
+            freeverb.handle_events(0, &mut next_event, || None);
+            assert!(next_event.is_none());
+
+            for _ in 0..fade {
+                freeverb.tick((0.0, 0.0));
+            }
+            assert_eq!(freeverb.freeze.previous_value(), frozen);
+        }
+    }
+
+    #[test]
+    fn equal_power_keeps_the_level() {
+        for mix in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            let (dry, wet) = MixLaw::EqualPower.blend(mix);
+
+            assert!((dry * dry + wet * wet - 1.0).abs() < 1e-6, "at {mix}");
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! The plate reverb from Jon Dattorro's "Effect Design, Part 1: Reverberator and Other Filters".
+//! The damping and decay of each half of the tank are replaced by a three band decay filter.
+
+use crate::tank::{Decay, DecayFilter, Diffuser};
+use plugin_common::{
+    delay_line::DelayLine,
+    lfo::{Lfo, LfoShape},
+};
+
+/// All lengths in the paper are given in samples at this rate
+const TUNING_SAMPLE_RATE: f32 = 29_761.0;
+
+/// Length and coefficient of the allpasses that smear the input
+const INPUT_DIFFUSERS: [(usize, f32); 4] = [(142, 0.75), (107, 0.75), (379, 0.625), (277, 0.625)];
+
+/// Modulated allpass, first delay, allpass and second delay of both halves of the tank
+const TANK: [[usize; 4]; 2] = [[672, 4453, 1800, 3720], [908, 4217, 2656, 3163]];
+const DECAY_DIFFUSION_1: f32 = -0.7;
+const DECAY_DIFFUSION_2: f32 = 0.5;
+
+#[derive(Clone, Copy)]
+enum Node {
+    FirstDelay,
+    Allpass,
+    SecondDelay,
+}
+
+/// Half of the tank, node, delay and sign of the output taps for the left and right channel
+#[rustfmt::skip]
+const TAPS: [[(usize, Node, usize, f32); 7]; 2] = [
+    [
+        (1, Node::FirstDelay, 266, 1.0),
+        (1, Node::FirstDelay, 2974, 1.0),
+        (1, Node::Allpass, 1913, -1.0),
+        (1, Node::SecondDelay, 1996, 1.0),
+        (0, Node::FirstDelay, 1990, -1.0),
+        (0, Node::Allpass, 187, -1.0),
+        (0, Node::SecondDelay, 1066, -1.0),
+    ],
+    [
+        (0, Node::FirstDelay, 353, 1.0),
+        (0, Node::FirstDelay, 3627, 1.0),
+        (0, Node::Allpass, 1228, -1.0),
+        (0, Node::SecondDelay, 2673, 1.0),
+        (1, Node::FirstDelay, 2111, -1.0),
+        (1, Node::Allpass, 335, -1.0),
+        (1, Node::SecondDelay, 121, -1.0),
+    ],
+];
+
+/// Brings the plate to about the loudness of the comb bank
+const INPUT_GAIN: f32 = 20.0;
+const OUTPUT_GAIN: f32 = 0.6;
+
+fn adjust_length(length: usize, sr: f32) -> usize {
+    ((length as f32 * sr / TUNING_SAMPLE_RATE).round() as usize).max(2)
+}
+
+struct Half {
+    modulated: Diffuser,
+    first_delay: (DelayLine, f32),
+    damping: DecayFilter,
+    allpass: Diffuser,
+    second_delay: (DelayLine, f32),
+    /// Loop time in seconds, for the decay gains
+    length: f32,
+    output: f32,
+}
+
+impl Half {
+    fn new(lengths: [usize; 4], headroom: usize, sr: f32) -> Half {
+        let [modulated, first, allpass, second] = lengths.map(|length| adjust_length(length, sr));
+
+        Half {
+            modulated: Diffuser::new(modulated, headroom, DECAY_DIFFUSION_1),
+            first_delay: (DelayLine::new(first), first as f32),
+            damping: DecayFilter::new(sr),
+            allpass: Diffuser::new(allpass, 0, DECAY_DIFFUSION_2),
+            second_delay: (DelayLine::new(second), second as f32),
+            length: (modulated + first + allpass + second) as f32 / sr,
+            output: 0.0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.modulated.clear();
+        self.first_delay.0.clear();
+        self.damping.clear();
+        self.allpass.clear();
+        self.second_delay.0.clear();
+        self.output = 0.0;
+    }
+
+    #[inline(always)]
+    fn tick(&mut self, input: f32, offset: f32) {
+        let diffused = self.modulated.tick(input, offset);
+
+        let delayed = self.first_delay.0.read(self.first_delay.1);
+        self.first_delay.0.write(diffused);
+
+        let diffused = self.allpass.tick(self.damping.tick(delayed), 0.0);
+
+        self.output = self.second_delay.0.read(self.second_delay.1);
+        self.second_delay.0.write(diffused);
+    }
+
+    #[inline(always)]
+    fn tap(&self, node: Node, delay: usize) -> f32 {
+        match node {
+            Node::FirstDelay => self.first_delay.0.read(delay as f32),
+            Node::Allpass => self.allpass.tap(delay),
+            Node::SecondDelay => self.second_delay.0.read(delay as f32),
+        }
+    }
+}
+
+pub struct Plate {
+    diffusers: [Diffuser; 4],
+    halves: [Half; 2],
+    taps: [[(usize, Node, usize, f32); 7]; 2],
+    decay: Decay,
+}
+
+impl Plate {
+    /// `headroom` is the deepest modulation in samples
+    pub fn new(sr: f32, headroom: usize) -> Plate {
+        Plate {
+            diffusers: INPUT_DIFFUSERS.map(|(length, coefficient)| {
+                Diffuser::new(adjust_length(length, sr), 0, coefficient)
+            }),
+            halves: TANK.map(|lengths| Half::new(lengths, headroom, sr)),
+            taps: TAPS.map(|taps| {
+                taps.map(|(half, node, delay, sign)| (half, node, adjust_length(delay, sr), sign))
+            }),
+            decay: Decay::default(),
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    pub fn set_decay(&mut self, decay: Decay) {
+        if decay == self.decay {
+            return;
+        }
+        self.decay = decay;
+
+        for half in self.halves.iter_mut() {
+            half.damping.set_gains(decay.gains(half.length));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.diffusers.iter_mut().for_each(Diffuser::clear);
+        self.halves.iter_mut().for_each(Half::clear);
+    }
+
+    /// `depth` is the modulation of the tank allpasses in samples
+    #[inline(always)]
+    pub fn tick(&mut self, input: f32, lfo: &Lfo, depth: f32) -> (f32, f32) {
+        let diffused = self
+            .diffusers
+            .iter_mut()
+            .fold(input * INPUT_GAIN, |sample, diffuser| {
+                diffuser.tick(sample, 0.0)
+            });
+
+        // each half is fed by the other one, so they have to be read before either is updated
+        let outputs = (self.halves[0].output, self.halves[1].output);
+        self.halves[0].tick(diffused + outputs.1, lfo.value(LfoShape::Sine, 0.0) * depth);
+        self.halves[1].tick(
+            diffused + outputs.0,
+            lfo.value(LfoShape::Sine, 0.25) * depth,
+        );
+
+        let [left, right] = self.taps.map(|taps| {
+            taps.iter()
+                .map(|&(half, node, delay, sign)| self.halves[half].tap(node, delay) * sign)
+                .sum::<f32>()
+                * OUTPUT_GAIN
+        });
+
+        (left, right)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tank::tests::decay_time;
+
+    #[test]
+    fn tail_follows_the_decay_time() {
+        for (sr, rt60) in [(44_100.0, 1.0), (48_000.0, 2.0), (96_000.0, 1.0)] {
+            let mut plate = Plate::new(sr, 0);
+            plate.set_decay(Decay {
+                time: rt60,
+                low: 1.0,
+                high: 1.0,
+                freeze: 0.0,
+            });
+
+            let lfo = Lfo::new(1);
+            let measured = decay_time(sr, |input| plate.tick(input, &lfo, 0.0));
+
+            assert!(
+                (measured / rt60 - 1.0).abs() < 0.2,
+                "{measured} s at {sr} Hz"
+            );
+        }
+    }
+}
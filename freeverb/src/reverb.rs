@@ -0,0 +1,615 @@
+//! Freeverb as described by Jezar at Dreampoint, with all delay lengths scaled to the sample rate.
+//! A pre-delay, filters on the input and on the wet signal and a slow modulation of the comb
+//! lengths come on top of the original. The plate and the hall can take the place of the comb
+//! and allpass bank and share everything else.
+
+use crate::{hall::Hall, plate::Plate, tank::Decay};
+use nih_plug::prelude::*;
+use plugin_common::{
+    delay_line::{DelayLine, MIN_DELAY},
+    filter::{Cut, HIGHEST_CUT, LOWEST_CUT},
+    lfo::{Lfo, LfoShape},
+};
+
+const NUM_COMBS: usize = 8;
+const NUM_ALLPASSES: usize = 4;
+
+/// Original tunings in samples at 44.1 kHz
+const COMB_TUNING: [usize; NUM_COMBS] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; NUM_ALLPASSES] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const TUNING_SAMPLE_RATE: f32 = 44_100.0;
+
+const FIXED_GAIN: f32 = 0.015;
+const SCALE_WET: f32 = 3.0;
+const SCALE_DRY: f32 = 2.0;
+const SCALE_DAMP: f32 = 0.4;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// In seconds
+pub const MAX_PRE_DELAY: f32 = 0.5;
+
+/// How far the comb lengths swing in either direction, in seconds
+pub const MAX_MOD_DEPTH: f32 = 0.002;
+
+#[derive(Enum, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Freeverb,
+    /// Dattorro's plate
+    Plate,
+    /// Feedback delay network
+    Hall,
+}
+
+/// Scales a delay length from the original tuning rate to `sr`
+pub fn adjust_length(length: usize, sr: f32) -> usize {
+    ((length as f32 * sr / TUNING_SAMPLE_RATE).round() as usize).max(1)
+}
+
+struct Comb {
+    buffer: Vec<f32>,
+    /// Delay in samples, the buffer is longer to leave room for the modulation
+    length: usize,
+    index: usize,
+    feedback: f32,
+    filter_state: f32,
+    damp1: f32,
+    damp2: f32,
+}
+
+impl Comb {
+    fn new(length: usize, headroom: usize) -> Comb {
+        Comb {
+            buffer: vec![0.0; length + headroom],
+            length,
+            index: 0,
+            feedback: 0.5,
+            filter_state: 0.0,
+            damp1: 0.5,
+            damp2: 0.5,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0.0);
+        self.filter_state = 0.0;
+    }
+
+    fn set_damp(&mut self, damp: f32) {
+        self.damp1 = damp;
+        self.damp2 = 1.0 - damp;
+    }
+
+    #[inline(always)]
+    fn tick(&mut self, input: f32) -> f32 {
+        let mut read = self.index + self.buffer.len() - self.length;
+        if read >= self.buffer.len() {
+            read -= self.buffer.len();
+        }
+
+        self.feed(self.buffer[read], input)
+    }
+
+    /// Reads `offset` samples further back than `tick`, interpolating between samples
+    #[inline(always)]
+    fn tick_modulated(&mut self, input: f32, offset: f32) -> f32 {
+        let len = self.buffer.len();
+        let position = (self.index + len) as f32 - (self.length as f32 + offset);
+
+        let i = position as usize;
+        let t = position - i as f32;
+        let (a, b) = (self.buffer[i % len], self.buffer[(i + 1) % len]);
+
+        self.feed(a + (b - a) * t, input)
+    }
+
+    /// Writes the filtered `output` back together with the `input` and moves on by one sample
+    #[inline(always)]
+    fn feed(&mut self, output: f32, input: f32) -> f32 {
+        self.filter_state = output * self.damp2 + self.filter_state * self.damp1;
+        self.buffer[self.index] = input + self.filter_state * self.feedback;
+
+        self.index += 1;
+        if self.index == self.buffer.len() {
+            self.index = 0;
+        }
+
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Allpass {
+        Allpass {
+            buffer: vec![0.0; length],
+            index: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.buffer.fill(0.0);
+    }
+
+    #[inline(always)]
+    fn tick(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+
+        self.buffer[self.index] = input + delayed * ALLPASS_FEEDBACK;
+
+        self.index += 1;
+        if self.index == self.buffer.len() {
+            self.index = 0;
+        }
+
+        delayed - input
+    }
+}
+
+pub struct Reverb {
+    pre_delay: DelayLine,
+    pre_delay_samples: f32,
+    input_filter: (Cut, Cut),
+
+    algorithm: Algorithm,
+    combs: [(Comb, Comb); NUM_COMBS],
+    allpasses: [(Allpass, Allpass); NUM_ALLPASSES],
+    plate: Plate,
+    hall: Hall,
+    /// Only used by the plate and the hall
+    decay: Decay,
+
+    /// Highpass and lowpass per channel
+    tone: [(Cut, Cut); 2],
+
+    lfo: Lfo,
+    /// In samples, the combs keep their fixed lengths at 0.0
+    mod_depth: f32,
+
+    sr: f32,
+    input_gain: f32,
+    wet_gains: (f32, f32),
+    dry: f32,
+}
+
+impl Reverb {
+    /// Allocates all delay lines, so don't call this on the audio thread
+    pub fn new(sr: f32) -> Reverb {
+        let spread = adjust_length(STEREO_SPREAD, sr);
+        // one extra sample on each side for the interpolation
+        let headroom = (MAX_MOD_DEPTH * sr).ceil() as usize + 2;
+
+        let mut reverb = Reverb {
+            pre_delay: DelayLine::new((MAX_PRE_DELAY * sr) as usize),
+            pre_delay_samples: 0.0,
+            input_filter: (Cut::new(), Cut::new()),
+
+            algorithm: Algorithm::Freeverb,
+            combs: COMB_TUNING.map(|length| {
+                let length = adjust_length(length, sr);
+                (
+                    Comb::new(length, headroom),
+                    Comb::new(length + spread, headroom),
+                )
+            }),
+            allpasses: ALLPASS_TUNING.map(|length| {
+                let length = adjust_length(length, sr);
+                (Allpass::new(length), Allpass::new(length + spread))
+            }),
+            plate: Plate::new(sr, headroom),
+            hall: Hall::new(sr, headroom),
+            decay: Decay {
+                time: 2.0,
+                low: 1.0,
+                high: 1.0,
+                freeze: 0.0,
+            },
+
+            tone: [(Cut::new(), Cut::new()), (Cut::new(), Cut::new())],
+
+            lfo: Lfo::new(1),
+            mod_depth: 0.0,
+
+            sr,
+
+            input_gain: FIXED_GAIN,
+            wet_gains: (0.0, 0.0),
+            dry: 0.0,
+        };
+
+        reverb.set_all(0.5, 0.5, 0.5, 0.0, 0.5, 0.5);
+        reverb.set_input_filter(LOWEST_CUT, HIGHEST_CUT);
+        reverb.set_tone(LOWEST_CUT, HIGHEST_CUT);
+
+        reverb
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// Clears the tank that takes over, so it doesn't play what's left from the last time it was
+    /// in use
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        if algorithm == self.algorithm {
+            return;
+        }
+        self.algorithm = algorithm;
+
+        match algorithm {
+            Algorithm::Freeverb => {
+                for (l, r) in self.combs.iter_mut() {
+                    l.clear();
+                    r.clear();
+                }
+                for (l, r) in self.allpasses.iter_mut() {
+                    l.clear();
+                    r.clear();
+                }
+            }
+            Algorithm::Plate => self.plate.clear(),
+            Algorithm::Hall => self.hall.clear(),
+        }
+        self.update_decay();
+    }
+
+    /// All values are normalized from 0.0 to 1.0, `damp` and `room_size` only affect Freeverb
+    ///
+    /// `freeze` is continuous, so toggling it can be faded in and out. `wet` and `dry` are
+    /// independent levels, just like in the original.
+    pub fn set_all(
+        &mut self,
+        damp: f32,
+        room_size: f32,
+        width: f32,
+        freeze: f32,
+        wet: f32,
+        dry: f32,
+    ) {
+        let feedback = room_size * SCALE_ROOM + OFFSET_ROOM;
+        let damp = damp * SCALE_DAMP;
+
+        // a frozen tank neither loses energy nor takes in new input
+        let feedback = feedback + (1.0 - feedback) * freeze;
+        let damp = damp * (1.0 - freeze);
+
+        for (l, r) in self.combs.iter_mut() {
+            l.feedback = feedback;
+            r.feedback = feedback;
+            l.set_damp(damp);
+            r.set_damp(damp);
+        }
+
+        if freeze != self.decay.freeze {
+            self.decay.freeze = freeze;
+            self.update_decay();
+        }
+        self.input_gain = FIXED_GAIN * (1.0 - freeze);
+
+        let wet = wet * SCALE_WET;
+        self.wet_gains = (wet * (width / 2.0 + 0.5), wet * ((1.0 - width) / 2.0));
+        self.dry = dry * SCALE_DRY;
+    }
+
+    /// Decay time in seconds of the plate and the hall, the lows and highs decay `low` and `high`
+    /// times as long
+    pub fn set_decay(&mut self, time: f32, low: f32, high: f32) {
+        let decay = Decay {
+            time,
+            low,
+            high,
+            ..self.decay
+        };
+
+        if decay != self.decay {
+            self.decay = decay;
+            self.update_decay();
+        }
+    }
+
+    pub fn set_pre_delay(&mut self, seconds: f32) {
+        self.pre_delay_samples = seconds.clamp(0.0, MAX_PRE_DELAY) * self.sr;
+    }
+
+    /// Filters what goes into the comb bank, `LOWEST_CUT` and `HIGHEST_CUT` switch them off
+    pub fn set_input_filter(&mut self, low_cut: f32, high_cut: f32) {
+        let (highpass, lowpass) = &mut self.input_filter;
+        highpass.set_cutoff(low_cut, low_cut > LOWEST_CUT, self.sr);
+        lowpass.set_cutoff(high_cut, high_cut < HIGHEST_CUT, self.sr);
+    }
+
+    /// Filters the wet signal, `LOWEST_CUT` and `HIGHEST_CUT` switch them off
+    pub fn set_tone(&mut self, low_cut: f32, high_cut: f32) {
+        for (highpass, lowpass) in self.tone.iter_mut() {
+            highpass.set_cutoff(low_cut, low_cut > LOWEST_CUT, self.sr);
+            lowpass.set_cutoff(high_cut, high_cut < HIGHEST_CUT, self.sr);
+        }
+    }
+
+    /// `depth` is normalized from 0.0 to 1.0, at 0.0 the output is the same as without modulation
+    pub fn set_modulation(&mut self, rate: f32, depth: f32) {
+        self.lfo.set_rate(rate, self.sr);
+        self.mod_depth = depth * MAX_MOD_DEPTH * self.sr;
+    }
+
+    pub fn tick(&mut self, input: (f32, f32)) -> (f32, f32) {
+        let mono = input.0 + input.1;
+        // the line can't delay by less than MIN_DELAY, so shorter pre-delays fade over from the
+        // input, which goes straight through at 0.0
+        let delayed = if self.pre_delay_samples < MIN_DELAY {
+            let fade = self.pre_delay_samples / MIN_DELAY;
+            mono + fade * (self.pre_delay.read(MIN_DELAY) - mono)
+        } else {
+            self.pre_delay.read(self.pre_delay_samples)
+        };
+        self.pre_delay.write(mono);
+
+        // the gain comes last, so freezing also stops what's still in the pre-delay
+        let filtered = self
+            .input_filter
+            .1
+            .lowpass(self.input_filter.0.highpass(delayed));
+        let mono = filtered * self.input_gain;
+
+        let mut out = match self.algorithm {
+            Algorithm::Freeverb => self.tick_freeverb(mono),
+            Algorithm::Plate => self.plate.tick(mono, &self.lfo, self.mod_depth),
+            Algorithm::Hall => self.hall.tick(mono, &self.lfo, self.mod_depth),
+        };
+
+        if self.mod_depth != 0.0 {
+            self.lfo.advance();
+        }
+
+        let [(highpass_l, lowpass_l), (highpass_r, lowpass_r)] = &mut self.tone;
+        out = (
+            lowpass_l.lowpass(highpass_l.highpass(out.0)),
+            lowpass_r.lowpass(highpass_r.highpass(out.1)),
+        );
+
+        (
+            out.0 * self.wet_gains.0 + out.1 * self.wet_gains.1 + input.0 * self.dry,
+            out.1 * self.wet_gains.0 + out.0 * self.wet_gains.1 + input.1 * self.dry,
+        )
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Private Functions
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// Hands the decay to the tank in use, which recalculates its gains with a powf per line
+    fn update_decay(&mut self) {
+        match self.algorithm {
+            Algorithm::Freeverb => (),
+            Algorithm::Plate => self.plate.set_decay(self.decay),
+            Algorithm::Hall => self.hall.set_decay(self.decay),
+        }
+    }
+
+    /// The original comb and allpass bank
+    #[inline(always)]
+    fn tick_freeverb(&mut self, mono: f32) -> (f32, f32) {
+        let mut out = (0.0, 0.0);
+
+        if self.mod_depth == 0.0 {
+            for (l, r) in self.combs.iter_mut() {
+                out.0 += l.tick(mono);
+                out.1 += r.tick(mono);
+            }
+        } else {
+            // every line gets its own phase, so they never stretch and shrink together
+            for (i, (l, r)) in self.combs.iter_mut().enumerate() {
+                let offset = i as f32 / NUM_COMBS as f32;
+                let (offset_l, offset_r) = (offset, offset + 0.5 / NUM_COMBS as f32);
+
+                out.0 += l.tick_modulated(
+                    mono,
+                    self.lfo.value(LfoShape::Sine, offset_l) * self.mod_depth,
+                );
+                out.1 += r.tick_modulated(
+                    mono,
+                    self.lfo.value(LfoShape::Sine, offset_r) * self.mod_depth,
+                );
+            }
+        }
+
+        for (l, r) in self.allpasses.iter_mut() {
+            out.0 = l.tick(out.0);
+            out.1 = r.tick(out.1);
+        }
+
+        out
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comb_lengths_follow_sample_rate() {
+        for (sr, first, last) in [
+            (44_100.0, 1116, 1617),
+            (48_000.0, 1215, 1760),
+            (88_200.0, 2232, 3234),
+            (96_000.0, 2429, 3520),
+        ] {
+            let reverb = Reverb::new(sr);
+            let spread = adjust_length(STEREO_SPREAD, sr);
+
+            assert_eq!(reverb.combs[0].0.length, first, "at {sr} Hz");
+            assert_eq!(reverb.combs[0].1.length, first + spread, "at {sr} Hz");
+            assert_eq!(reverb.combs[NUM_COMBS - 1].0.length, last, "at {sr} Hz");
+        }
+    }
+
+    #[test]
+    fn tail_length_is_independent_of_sample_rate() {
+        // the first echo of the shortest comb must always arrive after the same time
+        for sr in [44_100.0, 48_000.0, 88_200.0, 96_000.0] {
+            let mut reverb = Reverb::new(sr);
+            reverb.set_all(0.0, 0.5, 1.0, 0.0, 1.0, 0.0);
+
+            let first_echo = (0..sr as usize)
+                .position(|n| {
+                    let impulse = if n == 0 { 1.0 } else { 0.0 };
+                    reverb.tick((impulse, impulse)).0 != 0.0
+                })
+                .unwrap();
+
+            let seconds = first_echo as f32 / sr;
+            let expected = COMB_TUNING[0] as f32 / TUNING_SAMPLE_RATE;
+
+            assert!(
+                (seconds - expected).abs() < 0.0005,
+                "{seconds} s at {sr} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn pre_delay_postpones_the_tail() {
+        let sr = 48_000.0;
+        let first_echo = |pre_delay: f32| {
+            let mut reverb = Reverb::new(sr);
+            reverb.set_all(0.0, 0.5, 1.0, 0.0, 1.0, 0.0);
+            reverb.set_pre_delay(pre_delay);
+
+            (0..sr as usize)
+                .position(|n| {
+                    let impulse = if n == 0 { 1.0 } else { 0.0 };
+                    reverb.tick((impulse, impulse)).0 != 0.0
+                })
+                .unwrap()
+        };
+
+        assert_eq!(first_echo(0.3) - first_echo(0.1), (0.2 * sr) as usize);
+    }
+
+    #[test]
+    fn no_pre_delay_sounds_like_the_original() {
+        let mut reverb = Reverb::new(48_000.0);
+        // only the bare comb and allpass bank, the way Freeverb ran before the pre-delay
+        let mut original = Reverb::new(48_000.0);
+
+        let mut noise = 1_u32;
+        for _ in 0..48_000 {
+            noise ^= noise << 13;
+            noise ^= noise >> 17;
+            noise ^= noise << 5;
+            let input = noise as f32 / u32::MAX as f32 - 0.5;
+
+            let out = original.tick_freeverb((input + input) * FIXED_GAIN);
+            let (wet_1, wet_2) = original.wet_gains;
+            let expected = (
+                out.0 * wet_1 + out.1 * wet_2 + input * original.dry,
+                out.1 * wet_1 + out.0 * wet_2 + input * original.dry,
+            );
+
+            assert_eq!(reverb.tick((input, input)), expected);
+        }
+    }
+
+    #[test]
+    fn interpolated_read_matches_fixed_read_without_offset() {
+        let mut fixed = Comb::new(100, 10);
+        let mut modulated = Comb::new(100, 10);
+
+        for n in 0..1_000 {
+            let input = (n as f32 * 0.1).sin();
+            assert_eq!(fixed.tick(input), modulated.tick_modulated(input, 0.0));
+        }
+    }
+
+    #[test]
+    fn modulation_varies_the_tail() {
+        let sr = 48_000.0;
+        let render = |depth: f32| {
+            let mut reverb = Reverb::new(sr);
+            reverb.set_all(0.5, 0.8, 1.0, 0.0, 1.0, 0.0);
+            reverb.set_modulation(0.5, depth);
+
+            (0..sr as usize)
+                .map(|n| {
+                    let impulse = if n == 0 { 1.0 } else { 0.0 };
+                    reverb.tick((impulse, impulse))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let fixed = render(0.0);
+        let modulated = render(1.0);
+
+        assert_ne!(fixed, modulated);
+        assert!(modulated
+            .iter()
+            .all(|(l, r)| l.is_finite() && r.is_finite()));
+    }
+
+    #[test]
+    fn every_algorithm_holds_a_frozen_tail() {
+        let sr = 48_000.0;
+
+        for algorithm in [Algorithm::Freeverb, Algorithm::Plate, Algorithm::Hall] {
+            let mut reverb = Reverb::new(sr);
+            reverb.set_algorithm(algorithm);
+            reverb.set_decay(1.0, 1.0, 0.5);
+
+            let mut noise = 1_u32;
+            let mut level = |reverb: &mut Reverb, freeze: f32| {
+                reverb.set_all(0.5, 0.5, 1.0, freeze, 1.0, 0.0);
+
+                let energy: f32 = (0..sr as usize / 2)
+                    .map(|_| {
+                        noise ^= noise << 13;
+                        noise ^= noise >> 17;
+                        noise ^= noise << 5;
+                        let input = noise as f32 / u32::MAX as f32 - 0.5;
+
+                        let (l, r) = reverb.tick((input, input));
+                        l * l + r * r
+                    })
+                    .sum();
+
+                energy.sqrt()
+            };
+
+            level(&mut reverb, 0.0);
+            let frozen = level(&mut reverb, 1.0);
+            for _ in 0..4 {
+                let later = level(&mut reverb, 1.0);
+                assert!((later / frozen - 1.0).abs() < 0.1, "{later} after {frozen}");
+            }
+        }
+    }
+
+    #[test]
+    fn cuts_at_the_ends_of_their_range_are_switched_off() {
+        let mut reverb = Reverb::new(48_000.0);
+        reverb.set_tone(200.0, HIGHEST_CUT);
+
+        let [(highpass, lowpass), _] = &mut reverb.tone;
+        let (input_highpass, input_lowpass) = &mut reverb.input_filter;
+
+        let mut changed = false;
+        for n in 0..1_000 {
+            let input = (n as f32 * 0.01).sin();
+
+            assert_eq!(input_lowpass.lowpass(input_highpass.highpass(input)), input);
+            assert_eq!(lowpass.lowpass(input), input);
+            changed |= highpass.highpass(input) != input;
+        }
+
+        assert!(changed);
+    }
+}
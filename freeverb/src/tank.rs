@@ -0,0 +1,180 @@
+//! Building blocks of the plate and the hall.
+
+use plugin_common::{delay_line::DelayLine, filter::OnePole};
+
+/// Where the decay filters split lows, mids and highs, in Hz
+const LOW_CROSSOVER: f32 = 250.0;
+const HIGH_CROSSOVER: f32 = 4_000.0;
+
+/// Gain per pass through a loop of `delay` seconds, so that it falls by 60 dB within `rt60`
+pub fn decay_gain(delay: f32, rt60: f32) -> f32 {
+    0.001_f32.powf(delay / rt60)
+}
+
+/// Decay time, low and high multipliers and freeze, only recalculated when one of them changes
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Decay {
+    /// RT60 in seconds
+    pub time: f32,
+    pub low: f32,
+    pub high: f32,
+    /// From 0.0 to 1.0
+    pub freeze: f32,
+}
+
+impl Decay {
+    /// Gains of the lows, mids and highs for a loop of `delay` seconds
+    pub fn gains(&self, delay: f32) -> (f32, f32, f32) {
+        let gain = |multiplier: f32| {
+            let gain = decay_gain(delay, self.time * multiplier);
+            // a frozen tank never loses energy
+            gain + (1.0 - gain) * self.freeze
+        };
+
+        (gain(self.low), gain(1.0), gain(self.high))
+    }
+}
+
+/// Allpass on top of a delay line, which allows modulating the delay
+pub struct Diffuser {
+    line: DelayLine,
+    /// In samples
+    delay: f32,
+    coefficient: f32,
+}
+
+impl Diffuser {
+    /// `headroom` is the longest modulation in samples
+    pub fn new(delay: usize, headroom: usize, coefficient: f32) -> Diffuser {
+        Diffuser {
+            line: DelayLine::new(delay + headroom + 2),
+            delay: delay as f32,
+            coefficient,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.line.clear();
+    }
+
+    /// `offset` is added to the delay, in samples
+    #[inline(always)]
+    pub fn tick(&mut self, input: f32, offset: f32) -> f32 {
+        let delayed = self.line.read(self.delay + offset);
+        let v = input - self.coefficient * delayed;
+        self.line.write(v);
+
+        delayed + self.coefficient * v
+    }
+
+    /// Reads what went into the delay line `delay` samples ago, for output taps
+    #[inline(always)]
+    pub fn tap(&self, delay: usize) -> f32 {
+        self.line.read(delay as f32)
+    }
+}
+
+/// Splits a signal into lows, mids and highs and lets every band decay at its own rate
+pub struct DecayFilter {
+    low: OnePole,
+    high: OnePole,
+    gains: (f32, f32, f32),
+}
+
+impl DecayFilter {
+    pub fn new(sr: f32) -> DecayFilter {
+        let mut filter = DecayFilter {
+            low: OnePole::new(),
+            high: OnePole::new(),
+            gains: (1.0, 1.0, 1.0),
+        };
+        filter.low.set_cutoff(LOW_CROSSOVER, sr);
+        filter.high.set_cutoff(HIGH_CROSSOVER, sr);
+
+        filter
+    }
+
+    pub fn set_gains(&mut self, (low, mid, high): (f32, f32, f32)) {
+        self.gains = (low, mid, high);
+    }
+
+    pub fn clear(&mut self) {
+        self.low.reset();
+        self.high.reset();
+    }
+
+    #[inline(always)]
+    pub fn tick(&mut self, input: f32) -> f32 {
+        let low = self.low.lowpass(input);
+        let high = self.high.highpass(input);
+        let mid = input - low - high;
+
+        low * self.gains.0 + mid * self.gains.1 + high * self.gains.2
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    /// Measures the RT60 of the impulse response of `tick`, from the drop between -5 dB and
+    /// -35 dB of its backwards integrated energy
+    pub fn decay_time(sr: f32, mut tick: impl FnMut(f32) -> (f32, f32)) -> f32 {
+        let mut remaining: Vec<f64> = (0..6 * sr as usize)
+            .map(|n| {
+                let (l, r) = tick(if n == 0 { 1.0 } else { 0.0 });
+                (l * l + r * r) as f64
+            })
+            .collect();
+        for n in (0..remaining.len() - 1).rev() {
+            remaining[n] += remaining[n + 1];
+        }
+
+        let time_at = |db: f64| {
+            let level = remaining[0] * 10.0_f64.powf(db / 10.0);
+            remaining.iter().position(|&energy| energy < level).unwrap() as f32 / sr
+        };
+
+        2.0 * (time_at(-35.0) - time_at(-5.0))
+    }
+
+    /// Peak of a sine at `hz` after the filter settled
+    fn peak(filter: &mut DecayFilter, hz: f32) -> f32 {
+        (0..SR as usize)
+            .map(|n| filter.tick((n as f32 / SR * hz * std::f32::consts::TAU).sin()))
+            .skip(SR as usize / 2)
+            .fold(0.0, |peak: f32, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn decay_filter_applies_the_band_gains() {
+        let mut filter = DecayFilter::new(SR);
+
+        filter.set_gains((1.0, 1.0, 1.0));
+        for hz in [50.0, 1_000.0, 15_000.0] {
+            assert!((peak(&mut filter, hz) - 1.0).abs() < 0.01, "at {hz} Hz");
+        }
+
+        filter.set_gains((1.0, 0.5, 0.1));
+        assert!(peak(&mut filter, 20.0) > 0.95);
+        assert!(peak(&mut filter, 18_000.0) < 0.2);
+    }
+
+    #[test]
+    fn frozen_decay_keeps_everything() {
+        let decay = Decay {
+            time: 0.5,
+            low: 2.0,
+            high: 0.25,
+            freeze: 1.0,
+        };
+
+        assert_eq!(decay.gains(0.1), (1.0, 1.0, 1.0));
+    }
+}
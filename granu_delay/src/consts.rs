@@ -4,6 +4,9 @@ pub const MIN_DELAY_TIME: f32 = 0.01;
 /// in seconds
 pub const MAX_DELAY_TIME: f32 = 2.0;
 
+/// in milliseconds, for delay time, feedback and mix
+pub const SMOOTHING_TIME: f32 = 50.0;
+
 // Default parameter values
 pub const DEFAULT_DELAY_TIME_PERCTENTAGE: f32 = 0.1;
 pub const DEFAULT_FEEDBACK_PERCTENTAGE: f32 = 0.3;
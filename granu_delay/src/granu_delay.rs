@@ -1,10 +1,7 @@
 use crate::{params::GranuDelayParams, simple_delay::SimpleDelay};
 use granulator::{Granulator, UserSettings};
+use plugin_common::Stereo;
 use std::{sync::Arc, time::Instant};
-pub struct Stereo<T> {
-    pub l: T,
-    pub r: T,
-}
 
 pub struct GranuDelay {
     pub params: Arc<GranuDelayParams>,
@@ -20,18 +17,9 @@ impl Default for GranuDelay {
     fn default() -> Self {
         Self {
             params: Arc::new(GranuDelayParams::default()),
-            buffer: Stereo {
-                l: vec![],
-                r: vec![],
-            },
-            delay: Stereo {
-                l: SimpleDelay::init(),
-                r: SimpleDelay::init(),
-            },
-            granu: Stereo {
-                l: Granulator::new(48_000),
-                r: Granulator::new(48_000),
-            },
+            buffer: Stereo::from_fn(Vec::new),
+            delay: Stereo::from_fn(SimpleDelay::init),
+            granu: Stereo::from_fn(|| Granulator::new(48_000)),
             granu_settings: UserSettings::new_empty(),
             sr: 48_000.0,
             last_time: Instant::now(),
@@ -8,10 +8,25 @@ mod util;
 use granu_delay::GranuDelay;
 use nih_plug::prelude::*;
 
+impl ClapPlugin for GranuDelay {
+    const CLAP_ID: &'static str = "de.maxgenson.granu-delay";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("Stereo delay with a granulator on top of the delay line");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Delay,
+        ClapFeature::Granular,
+    ];
+}
+
 impl Vst3Plugin for GranuDelay {
     const VST3_CLASS_ID: [u8; 16] = *b"GranuDelayMG....";
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
         &[Vst3SubCategory::Fx, Vst3SubCategory::Delay];
 }
 
+nih_export_clap!(GranuDelay);
 nih_export_vst3!(GranuDelay);
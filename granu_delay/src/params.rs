@@ -1,5 +1,6 @@
 use crate::consts;
 use nih_plug::prelude::*;
+use plugin_common::param::{percentage, seconds};
 
 #[derive(Params)]
 pub struct GranuDelayParams {
@@ -70,7 +71,7 @@ impl Default for GranuDelayParams {
     fn default() -> Self {
         GranuDelayParams {
             delay: DelayParams {
-                time_l: FloatParam::new(
+                time_l: seconds(
                     "L Delay",
                     consts::DEFAULT_DELAY_TIME_PERCTENTAGE,
                     FloatRange::Skewed {
@@ -79,10 +80,9 @@ impl Default for GranuDelayParams {
                         factor: 0.5,
                     },
                 )
-                .with_unit(" s")
-                .with_value_to_string(formatters::v2s_f32_rounded(3)),
+                .with_smoother(SmoothingStyle::Linear(consts::SMOOTHING_TIME)),
 
-                time_r: FloatParam::new(
+                time_r: seconds(
                     "R Delay",
                     consts::DEFAULT_DELAY_TIME_PERCTENTAGE,
                     FloatRange::Skewed {
@@ -91,71 +91,21 @@ impl Default for GranuDelayParams {
                         factor: 0.5,
                     },
                 )
-                .with_unit(" s")
-                .with_value_to_string(formatters::v2s_f32_rounded(3)),
+                .with_smoother(SmoothingStyle::Linear(consts::SMOOTHING_TIME)),
 
-                feedback: FloatParam::new(
-                    "Feedback",
-                    consts::DEFAULT_FEEDBACK_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
+                feedback: percentage("Feedback", consts::DEFAULT_FEEDBACK_PERCTENTAGE, 2)
+                    .with_smoother(SmoothingStyle::Linear(consts::SMOOTHING_TIME)),
             },
 
             granu: GranuParams {
-                rand_delay: FloatParam::new(
-                    "Spray",
-                    consts::DEFAULT_SPRAY_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
-                n_grains: FloatParam::new(
-                    "Density",
-                    consts::DEFAULT_DENSITY_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
-                rand_pitch: FloatParam::new(
-                    "Chaos",
-                    consts::DEFAULT_CHAOS_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
-                pitch: FloatParam::new("Pitch", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
-                    .with_value_to_string(formatters::v2s_f32_percentage(2))
-                    .with_unit(" %"),
-                rand_grain_length: FloatParam::new(
-                    "Space",
-                    consts::DEFAULT_SPACE_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
-                grain_length: FloatParam::new(
-                    "Room",
-                    consts::DEFAULT_ROOM_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
-                rand_buffer_offset: FloatParam::new(
-                    "Bend",
-                    consts::DEFAULT_BEND_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
-                buffer_offset: FloatParam::new(
-                    "Gravity",
-                    consts::DEFAULT_GRAVITY_PERCTENTAGE,
-                    FloatRange::Linear { min: 0.0, max: 1.0 },
-                )
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
+                rand_delay: percentage("Spray", consts::DEFAULT_SPRAY_PERCTENTAGE, 2),
+                n_grains: percentage("Density", consts::DEFAULT_DENSITY_PERCTENTAGE, 2),
+                rand_pitch: percentage("Chaos", consts::DEFAULT_CHAOS_PERCTENTAGE, 2),
+                pitch: percentage("Pitch", 0.5, 2),
+                rand_grain_length: percentage("Space", consts::DEFAULT_SPACE_PERCTENTAGE, 2),
+                grain_length: percentage("Room", consts::DEFAULT_ROOM_PERCTENTAGE, 2),
+                rand_buffer_offset: percentage("Bend", consts::DEFAULT_BEND_PERCTENTAGE, 2),
+                buffer_offset: percentage("Gravity", consts::DEFAULT_GRAVITY_PERCTENTAGE, 2),
             },
 
             // Boolean States
@@ -168,13 +118,8 @@ impl Default for GranuDelayParams {
             quantize: BoolParam::new("Quantize", false),
 
             // Util
-            mix: FloatParam::new(
-                "Dry/Wet",
-                consts::DEFAULT_DRY_WET_MIX_PERCTENTAGE,
-                FloatRange::Linear { min: 0.0, max: 1.0 },
-            )
-            .with_value_to_string(formatters::v2s_f32_percentage(0))
-            .with_unit(" %"),
+            mix: percentage("Dry/Wet", consts::DEFAULT_DRY_WET_MIX_PERCTENTAGE, 0)
+                .with_smoother(SmoothingStyle::Linear(consts::SMOOTHING_TIME)),
         }
     }
 }
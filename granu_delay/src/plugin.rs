@@ -1,22 +1,14 @@
 use nih_plug::prelude::*;
+use plugin_common::{plugin_info, stereo_frame, Stereo, STEREO_IO_LAYOUTS};
 use std::{sync::Arc, time::Instant};
 use yanel_dsp::DSPUtility;
 
 use crate::{consts, granu_delay::GranuDelay, util::apply_granu_settings};
 
 impl Plugin for GranuDelay {
-    const NAME: &'static str = "Granular Delay";
-    const VENDOR: &'static str = "Max Genson";
-    const URL: &'static str = "https://www.maxgenson.de";
-    const EMAIL: &'static str = "mail@maxgenson.de";
+    plugin_info!("Granular Delay");
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
-        ..AudioIOLayout::const_default()
-    }];
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = STEREO_IO_LAYOUTS;
 
     const MIDI_INPUT: MidiConfig = MidiConfig::None;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -36,8 +28,8 @@ impl Plugin for GranuDelay {
     ) -> bool {
         self.sr = buffer_config.sample_rate;
 
-        self.buffer.l = vec![0_f32; consts::MAX_DELAY_TIME.seconds_to_samples(self.sr) as usize];
-        self.buffer.r = vec![0_f32; consts::MAX_DELAY_TIME.seconds_to_samples(self.sr) as usize];
+        let buffer_len = consts::MAX_DELAY_TIME.seconds_to_samples(self.sr) as usize;
+        self.buffer = Stereo::splat(vec![0_f32; buffer_len]);
 
         self.delay.l.set_buffer(self.buffer.l.as_mut_slice());
         self.delay.r.set_buffer(self.buffer.r.as_mut_slice());
@@ -59,33 +51,8 @@ impl Plugin for GranuDelay {
         _aux: &mut AuxiliaryBuffers,
         _context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        let n_samples = buffer.samples() as u32;
         let now = Instant::now();
 
-        self.delay.l.set_delay(
-            self.params
-                .delay
-                .time_l
-                .smoothed
-                .next_step(n_samples)
-                .seconds_to_samples(self.sr),
-        );
-        self.delay.r.set_delay(
-            self.params
-                .delay
-                .time_r
-                .smoothed
-                .next_step(n_samples)
-                .seconds_to_samples(self.sr),
-        );
-
-        let feedback = self.params.delay.feedback.smoothed.next_step(n_samples);
-
-        self.delay.l.set_feedback(feedback);
-        self.delay.r.set_feedback(feedback);
-
-        let mix = self.params.mix.smoothed.next_step(n_samples);
-
         apply_granu_settings(&mut self.granu_settings, &self.params, &mut self.granu.l);
         apply_granu_settings(&mut self.granu_settings, &self.params, &mut self.granu.r);
 
@@ -95,10 +62,17 @@ impl Plugin for GranuDelay {
         self.last_time = now;
 
         for channel_samples in buffer.iter_samples() {
-            let mut samples = channel_samples.into_iter();
-            let (l_out, r_out) = (samples.next().unwrap(), samples.next().unwrap());
+            let (l_out, r_out) = stereo_frame(channel_samples);
             let (l_in, r_in) = (l_out.clone(), r_out.clone());
 
+            self.update_delay_times();
+
+            let feedback = self.params.delay.feedback.smoothed.next();
+            self.delay.l.set_feedback(feedback);
+            self.delay.r.set_feedback(feedback);
+
+            let mix = self.params.mix.smoothed.next();
+
             let (mut l_sample, mut r_sample) = (0.0, 0.0);
 
             if self.params.enable_delay.value() {
@@ -114,12 +88,8 @@ impl Plugin for GranuDelay {
                 self.delay.l.advance_on_delay_line();
                 self.delay.r.advance_on_delay_line();
             } else {
-                self.delay
-                    .l
-                    .add_to_delay_line(l_in + l_sample * self.params.delay.feedback.value());
-                self.delay
-                    .r
-                    .add_to_delay_line(r_in + r_sample * self.params.delay.feedback.value());
+                self.delay.l.add_to_delay_line(l_in + l_sample * feedback);
+                self.delay.r.add_to_delay_line(r_in + r_sample * feedback);
             }
 
             // dry/wet mixing
@@ -134,3 +104,62 @@ impl Plugin for GranuDelay {
 
     fn deactivate(&mut self) {}
 }
+
+impl GranuDelay {
+    /// Smoothed every sample, so automation doesn't step at the block boundaries
+    fn update_delay_times(&mut self) {
+        let delay = &self.params.delay;
+
+        self.delay
+            .l
+            .set_delay(delay.time_l.smoothed.next().seconds_to_samples(self.sr));
+        self.delay
+            .r
+            .set_delay(delay.time_r.smoothed.next().seconds_to_samples(self.sr));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    #[test]
+    fn delay_time_automation_is_continuous() {
+        let mut plugin = GranuDelay::default();
+        let sr = plugin.sr;
+
+        plugin.buffer = Stereo::splat(vec![0.0; sr as usize]);
+        plugin.delay.l.set_buffer(plugin.buffer.l.as_mut_slice());
+        plugin.delay.r.set_buffer(plugin.buffer.r.as_mut_slice());
+
+        // an automation point from 100 ms down to 50 ms, the smoother has to turn it into a ramp
+        let time = &plugin.params.delay.time_l;
+        time.smoothed.reset(0.1);
+        time.smoothed.set_target(sr, 0.05);
+
+        let mut last = 0.0;
+        for n in 0..sr as usize {
+            plugin.update_delay_times();
+
+            let out = plugin.delay.l.get_delayed_sample();
+            plugin
+                .delay
+                .l
+                .add_to_delay_line((TAU * 100.0 * n as f32 / sr).sin() * 0.5);
+
+            // the steepest slope of the sine is below 0.007, the ramp plays it back twice as fast
+            assert!(
+                (out - last).abs() < 0.02,
+                "jumped from {last} to {out} at {n}"
+            );
+            last = out;
+        }
+
+        assert_eq!(plugin.params.delay.time_l.smoothed.previous_value(), 0.05);
+    }
+}
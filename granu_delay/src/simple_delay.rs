@@ -38,7 +38,7 @@ impl SimpleDelay {
     }
 
     ///////////////////////////////////////////////////////////////////////////////
-    /// Public Interface
+    // Public Interface
     ///////////////////////////////////////////////////////////////////////////////
 
     pub fn _tick(&mut self, input: f32) -> f32 {
@@ -110,7 +110,7 @@ impl SimpleDelay {
     }
 
     ///////////////////////////////////////////////////////////////////////////////
-    /// Private Functions
+    // Private Functions
     ///////////////////////////////////////////////////////////////////////////////
 
     #[inline(always)]
@@ -120,7 +120,7 @@ impl SimpleDelay {
 }
 
 ///////////////////////////////////////////////////////////////////////////////
-/// Unit Tests
+// Unit Tests
 ///////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
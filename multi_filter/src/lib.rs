@@ -1,6 +1,18 @@
 use nih_plug::prelude::*;
-use nih_plug::util;
-use std::sync::Arc;
+use plugin_common::{
+    check_sample_rate,
+    param::{gain_db, hz},
+    plugin_info,
+    stereo::{from_mid_side, to_mid_side},
+    stereo_frame, Stereo, STEREO_IO_LAYOUTS,
+};
+use std::{ops::RangeInclusive, sync::Arc};
+
+/// Below 22.05 kHz the cutoff range would be cut far below its maximum
+const SUPPORTED_SAMPLE_RATES: RangeInclusive<f32> = 22_050.0..=384_000.0;
+
+/// Keeps the cutoff safely below nyquist
+const MAX_CUTOFF_RATIO: f32 = 0.45;
 
 #[derive(Enum, PartialEq)]
 enum FilterType {
@@ -12,9 +24,21 @@ enum FilterType {
     LowShelf,
 }
 
+#[derive(Enum, PartialEq)]
+enum ChannelMode {
+    Stereo,
+    #[name = "Left Only"]
+    Left,
+    #[name = "Right Only"]
+    Right,
+    Mid,
+    Side,
+}
+
 struct Filters {
     params: Arc<LowpassParams>,
-    filter: yanel_dsp::MultiFilter,
+    filter: Stereo<yanel_dsp::MultiFilter>,
+    sr: f32,
 }
 
 #[derive(Params)]
@@ -30,13 +54,17 @@ struct LowpassParams {
 
     #[id = "Gain"]
     pub gain: FloatParam,
+
+    #[id = "Channel Mode"]
+    pub channel_mode: EnumParam<ChannelMode>,
 }
 
 impl Default for Filters {
     fn default() -> Self {
         Self {
             params: Arc::new(LowpassParams::default()),
-            filter: yanel_dsp::MultiFilter::init(48_000),
+            filter: Stereo::from_fn(|| yanel_dsp::MultiFilter::init(48_000)),
+            sr: 48_000.0,
         }
     }
 }
@@ -45,16 +73,7 @@ impl Default for LowpassParams {
     fn default() -> Self {
         Self {
             filter_type: EnumParam::new("Filter Type", FilterType::Lowpass),
-            cutoff: FloatParam::new(
-                "Cutoff",
-                5_100.0,
-                FloatRange::Skewed {
-                    min: 20.0,
-                    max: 20_000.0,
-                    factor: 0.5,
-                },
-            )
-            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2)),
+            cutoff: hz("Cutoff", 5_100.0, 20.0, 20_000.0),
 
             q: FloatParam::new(
                 "Q",
@@ -68,35 +87,17 @@ impl Default for LowpassParams {
             )
             .with_value_to_string(formatters::v2s_f32_rounded(2)),
 
-            gain: FloatParam::new(
-                "Gain",
-                1.0,
-                FloatRange::Skewed {
-                    min: util::db_to_gain(-20.0),
-                    max: util::db_to_gain(20.0),
-                    factor: FloatRange::gain_skew_factor(-20.0, 20.0),
-                },
-            )
-            .with_unit(" dB")
-            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
-            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+            gain: gain_db("Gain", 0.0, -20.0, 20.0),
+
+            channel_mode: EnumParam::new("Channel Mode", ChannelMode::Stereo),
         }
     }
 }
 
 impl Plugin for Filters {
-    const NAME: &'static str = "Multi Filter";
-    const VENDOR: &'static str = "Max Genson";
-    const URL: &'static str = "https://www.maxgenson.de";
-    const EMAIL: &'static str = "mail@maxgenson.de";
-
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    plugin_info!("Multi Filter");
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
-        ..AudioIOLayout::const_default()
-    }];
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = STEREO_IO_LAYOUTS;
 
     const MIDI_INPUT: MidiConfig = MidiConfig::None;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -108,6 +109,21 @@ impl Plugin for Filters {
         self.params.clone()
     }
 
+    fn initialize(
+        &mut self,
+        _audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        if !check_sample_rate(buffer_config.sample_rate, SUPPORTED_SAMPLE_RATES) {
+            return false;
+        }
+
+        self.set_sample_rate(buffer_config.sample_rate);
+
+        true
+    }
+
     fn process(
         &mut self,
         buffer: &mut Buffer,
@@ -115,16 +131,8 @@ impl Plugin for Filters {
         _context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
         for channel_samples in buffer.iter_samples() {
-            self.filter.set_all(
-                self.params.filter_type.value() as u8,
-                self.params.cutoff.smoothed.next(),
-                self.params.q.smoothed.next(),
-                self.params.gain.smoothed.next(),
-            );
-
-            for sample in channel_samples {
-                *sample = self.filter.next(*sample);
-            }
+            let (left, right) = stereo_frame(channel_samples);
+            (*left, *right) = self.tick((*left, *right));
         }
 
         ProcessStatus::Normal
@@ -133,6 +141,57 @@ impl Plugin for Filters {
     fn deactivate(&mut self) {}
 }
 
+impl Filters {
+    /// Rebuilds both channel filters, which also clears their state
+    fn set_sample_rate(&mut self, sr: f32) {
+        self.sr = sr;
+        self.filter = Stereo::from_fn(|| yanel_dsp::MultiFilter::init(sr as usize));
+    }
+
+    /// Updates both channel filters and processes one stereo frame according to the channel mode
+    fn tick(&mut self, (left, right): (f32, f32)) -> (f32, f32) {
+        let filter_type = self.params.filter_type.value() as u8;
+        let cutoff = self
+            .params
+            .cutoff
+            .smoothed
+            .next()
+            .min(self.sr * MAX_CUTOFF_RATIO);
+        let q = self.params.q.smoothed.next();
+        let gain = self.params.gain.smoothed.next();
+
+        self.filter.l.set_all(filter_type, cutoff, q, gain);
+        self.filter.r.set_all(filter_type, cutoff, q, gain);
+
+        match self.params.channel_mode.value() {
+            ChannelMode::Stereo => (self.filter.l.next(left), self.filter.r.next(right)),
+            ChannelMode::Left => (self.filter.l.next(left), right),
+            ChannelMode::Right => (left, self.filter.r.next(right)),
+            ChannelMode::Mid => {
+                let (mid, side) = to_mid_side((left, right));
+                from_mid_side((self.filter.l.next(mid), side))
+            }
+            ChannelMode::Side => {
+                let (mid, side) = to_mid_side((left, right));
+                from_mid_side((mid, self.filter.r.next(side)))
+            }
+        }
+    }
+}
+
+impl ClapPlugin for Filters {
+    const CLAP_ID: &'static str = "de.maxgenson.multi-filter";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("State variable filter with common filter types and per channel routing");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Filter,
+    ];
+}
+
 impl Vst3Plugin for Filters {
     const VST3_CLASS_ID: [u8; 16] = *b"MultiFilterMG...";
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
@@ -142,4 +201,81 @@ impl Vst3Plugin for Filters {
     ];
 }
 
+nih_export_clap!(Filters);
 nih_export_vst3!(Filters);
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nih_plug::util;
+
+    /// Without a host nobody initializes the smoothers, so do it by hand
+    fn reset_smoothers(params: &LowpassParams) {
+        params.cutoff.smoothed.reset(params.cutoff.value());
+        params.q.smoothed.reset(params.q.value());
+        params.gain.smoothed.reset(params.gain.value());
+    }
+
+    #[test]
+    fn left_impulse_does_not_leak_into_right() {
+        let mut filters = Filters::default();
+        reset_smoothers(&filters.params);
+
+        let mut left_energy = 0.0;
+
+        for n in 0..4_800 {
+            let impulse = if n == 0 { 1.0 } else { 0.0 };
+            let (left, right) = filters.tick((impulse, 0.0));
+
+            left_energy += left * left;
+            assert_eq!(right, 0.0, "right channel leaked at sample {n}");
+        }
+
+        assert!(left_energy > 0.0);
+    }
+
+    #[test]
+    fn cutoff_is_on_pitch_at_any_sample_rate() {
+        const CUTOFF: f32 = 1_000.0;
+
+        for sr in [44_100.0, 48_000.0, 88_200.0, 96_000.0] {
+            let mut filters = Filters::default();
+            filters.set_sample_rate(sr);
+            reset_smoothers(&filters.params);
+
+            // a butterworth lowpass is exactly 3 dB down at its cutoff
+            filters.params.cutoff.smoothed.reset(CUTOFF);
+            filters
+                .params
+                .q
+                .smoothed
+                .reset(std::f32::consts::FRAC_1_SQRT_2);
+
+            let n_samples = sr as usize;
+            let mut sum_in = 0.0;
+            let mut sum_out = 0.0;
+
+            for n in 0..n_samples {
+                let phase = std::f32::consts::TAU * CUTOFF * n as f32 / sr;
+                let input = phase.sin();
+                let (left, _) = filters.tick((input, input));
+
+                // skip the transient response
+                if n >= n_samples / 2 {
+                    sum_in += input * input;
+                    sum_out += left * left;
+                }
+            }
+
+            let measured_db = util::gain_to_db((sum_out / sum_in).sqrt());
+            assert!(
+                (measured_db + 3.01).abs() < 0.5,
+                "{measured_db} dB at the cutoff for {sr} Hz"
+            );
+        }
+    }
+}
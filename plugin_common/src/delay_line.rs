@@ -0,0 +1,103 @@
+//! Delay line with separate read and write steps, so the plugin decides what goes back in.
+
+/// The interpolation needs one sample after the read position that's already written
+pub const MIN_DELAY: f32 = 2.0;
+
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_index: usize,
+}
+
+impl DelayLine {
+    /// Allocates the buffer, so don't call this on the audio thread
+    pub fn new(length: usize) -> DelayLine {
+        DelayLine {
+            // one extra sample, so the full length can be read before it gets overwritten
+            buffer: vec![0.0; length.max(1) + 1],
+            write_index: 0,
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// Reads the sample written `delay` samples ago, at least two samples
+    ///
+    /// Fractional delays are interpolated with a cubic Hermite spline, which keeps the highs of
+    /// modulated delays. Call this before `write()` within the same sample.
+    #[inline(always)]
+    pub fn read(&self, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let delay = delay.clamp(MIN_DELAY, (len - 1) as f32);
+
+        let position = self.write_index as f32 + len as f32 - delay;
+        let index = position as usize;
+        let t = position - index as f32;
+
+        let x0 = self.buffer[(index + len - 1) % len];
+        let x1 = self.buffer[index % len];
+        let x2 = self.buffer[(index + 1) % len];
+        let x3 = self.buffer[(index + 2) % len];
+
+        let c1 = 0.5 * (x2 - x0);
+        let c2 = x0 - 2.5 * x1 + 2.0 * x2 - 0.5 * x3;
+        let c3 = 0.5 * (x3 - x0) + 1.5 * (x1 - x2);
+
+        ((c3 * t + c2) * t + c1) * t + x1
+    }
+
+    /// Silences everything that's still in the line
+    pub fn clear(&mut self) {
+        self.buffer.fill(0.0);
+    }
+
+    #[inline(always)]
+    pub fn write(&mut self, sample: f32) {
+        self.buffer[self.write_index] = sample;
+
+        self.write_index += 1;
+        if self.write_index == self.buffer.len() {
+            self.write_index = 0;
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impulse_arrives_after_delay() {
+        for delay in [2, 10, 100] {
+            let mut line = DelayLine::new(100);
+
+            let arrival = (0..200)
+                .position(|n| {
+                    let out = line.read(delay as f32);
+                    line.write(if n == 0 { 1.0 } else { 0.0 });
+                    out == 1.0
+                })
+                .unwrap();
+
+            assert_eq!(arrival, delay);
+        }
+    }
+
+    #[test]
+    fn fractional_delays_are_interpolated() {
+        let mut line = DelayLine::new(10);
+        for sample in [0.0, 1.0, 2.0, 3.0, 4.0] {
+            line.write(sample);
+        }
+
+        // a straight line stays straight
+        assert_eq!(line.read(2.0), 3.0);
+        assert_eq!(line.read(2.5), 2.5);
+        assert_eq!(line.read(3.25), 1.75);
+    }
+}
@@ -0,0 +1,131 @@
+//! Filters that are cheap enough to put anywhere.
+
+use std::f32::consts::PI;
+
+/// Keeps the filter cutoffs safely below nyquist
+pub const MAX_CUTOFF_RATIO: f32 = 0.45;
+
+/// Range of the low and high cuts in Hz, at either end they're switched off
+pub const LOWEST_CUT: f32 = 20.0;
+pub const HIGHEST_CUT: f32 = 20_000.0;
+
+/// Zero delay feedback one pole filter, 6 dB per octave
+#[derive(Default)]
+pub struct OnePole {
+    g: f32,
+    state: f32,
+}
+
+impl OnePole {
+    pub fn new() -> OnePole {
+        OnePole { g: 0.0, state: 0.0 }
+    }
+
+    pub fn set_cutoff(&mut self, cutoff: f32, sr: f32) {
+        let g = (PI * cutoff.min(sr * MAX_CUTOFF_RATIO) / sr).tan();
+        self.g = g / (1.0 + g);
+    }
+
+    pub fn reset(&mut self) {
+        self.state = 0.0;
+    }
+
+    #[inline(always)]
+    pub fn lowpass(&mut self, input: f32) -> f32 {
+        let v = (input - self.state) * self.g;
+        let output = v + self.state;
+        self.state = output + v;
+
+        output
+    }
+
+    #[inline(always)]
+    pub fn highpass(&mut self, input: f32) -> f32 {
+        input - self.lowpass(input)
+    }
+}
+
+/// One pole low or high cut that leaves the signal untouched while it's switched off
+#[derive(Default)]
+pub struct Cut {
+    filter: OnePole,
+    active: bool,
+}
+
+impl Cut {
+    pub fn new() -> Cut {
+        Cut {
+            filter: OnePole::new(),
+            active: false,
+        }
+    }
+
+    pub fn set_cutoff(&mut self, cutoff: f32, active: bool, sr: f32) {
+        // whatever the filter remembers from before it was switched off is stale by now
+        if active && !self.active {
+            self.filter.reset();
+        }
+        self.active = active;
+        self.filter.set_cutoff(cutoff, sr);
+    }
+
+    #[inline(always)]
+    pub fn highpass(&mut self, input: f32) -> f32 {
+        if self.active {
+            self.filter.highpass(input)
+        } else {
+            input
+        }
+    }
+
+    #[inline(always)]
+    pub fn lowpass(&mut self, input: f32) -> f32 {
+        if self.active {
+            self.filter.lowpass(input)
+        } else {
+            input
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    #[test]
+    fn lowpass_passes_dc_and_blocks_nyquist() {
+        let mut filter = OnePole::new();
+        filter.set_cutoff(1_000.0, SR);
+
+        let dc = (0..1_000).map(|_| filter.lowpass(1.0)).last().unwrap();
+        assert!((dc - 1.0).abs() < 1e-4, "{dc}");
+
+        // alternating samples are a sine at nyquist
+        let nyquist = (0..1_000)
+            .map(|n| filter.lowpass(if n % 2 == 0 { 1.0 } else { -1.0 }))
+            .skip(100)
+            .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        assert!(nyquist < 1e-3, "{nyquist}");
+    }
+
+    #[test]
+    fn highpass_is_the_complement_of_lowpass() {
+        let mut lowpass = OnePole::new();
+        let mut highpass = OnePole::new();
+        lowpass.set_cutoff(200.0, SR);
+        highpass.set_cutoff(200.0, SR);
+
+        for n in 0..1_000 {
+            let input = (n as f32 * 0.1).sin();
+            let sum = lowpass.lowpass(input) + highpass.highpass(input);
+
+            assert!((sum - input).abs() < 1e-5);
+        }
+    }
+}
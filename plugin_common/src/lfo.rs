@@ -0,0 +1,165 @@
+//! Low frequency oscillator for modulating delay times and the like, one value per sample.
+
+use nih_plug::prelude::*;
+use std::f32::consts::TAU;
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    /// Random values, smoothly interpolated once per cycle
+    Random,
+}
+
+pub struct Lfo {
+    /// From 0.0 to 1.0
+    phase: f32,
+    increment: f32,
+
+    rng_state: u32,
+    /// The current random segment goes from `random[0]` to `random[1]`, the third value is needed
+    /// to read up to one cycle ahead
+    random: [f32; 3],
+}
+
+impl Lfo {
+    /// Different seeds give different random shapes
+    pub fn new(seed: u32) -> Lfo {
+        let mut lfo = Lfo {
+            phase: 0.0,
+            increment: 0.0,
+
+            // xorshift gets stuck at zero
+            rng_state: seed.max(1),
+            random: [0.0; 3],
+        };
+
+        lfo.random = [lfo.next_random(), lfo.next_random(), lfo.next_random()];
+
+        lfo
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    pub fn set_rate(&mut self, hz: f32, sr: f32) {
+        self.increment = hz / sr;
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    /// Bipolar value from -1.0 to 1.0, `offset` is in cycles from 0.0 to 1.0 ahead of the phase
+    #[inline(always)]
+    pub fn value(&self, shape: LfoShape, offset: f32) -> f32 {
+        let phase = self.phase + offset.clamp(0.0, 1.0);
+
+        match shape {
+            LfoShape::Sine => (phase * TAU).sin(),
+            LfoShape::Triangle => {
+                // starts at zero and rises first, just like the sine
+                let phase = phase.fract();
+
+                if phase < 0.25 {
+                    4.0 * phase
+                } else if phase < 0.75 {
+                    2.0 - 4.0 * phase
+                } else {
+                    4.0 * phase - 4.0
+                }
+            }
+            LfoShape::Random => {
+                let segment = (phase as usize).min(1);
+                let t = phase - segment as f32;
+                let (from, to) = (self.random[segment], self.random[segment + 1]);
+
+                // smoothstep, so there are no corners at the segment borders
+                from + (to - from) * t * t * (3.0 - 2.0 * t)
+            }
+        }
+    }
+
+    /// Moves on by one sample
+    #[inline(always)]
+    pub fn advance(&mut self) {
+        self.phase += self.increment;
+
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.random = [self.random[1], self.random[2], self.next_random()];
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Private Functions
+    ///////////////////////////////////////////////////////////////////////////////
+
+    fn next_random(&mut self) -> f32 {
+        // xorshift32
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+
+        self.rng_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 1_000.0;
+
+    #[test]
+    fn shapes_start_at_zero_and_peak_after_a_quarter() {
+        for shape in [LfoShape::Sine, LfoShape::Triangle] {
+            let lfo = Lfo::new(1);
+
+            assert!(lfo.value(shape, 0.0).abs() < 1e-6);
+            assert!((lfo.value(shape, 0.25) - 1.0).abs() < 1e-6);
+            assert!((lfo.value(shape, 0.5)).abs() < 1e-6);
+            assert!((lfo.value(shape, 0.75) + 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn random_shape_is_smooth() {
+        let mut lfo = Lfo::new(1234);
+        lfo.set_rate(10.0, SR);
+
+        let mut last = lfo.value(LfoShape::Random, 0.0);
+        for _ in 0..10 * SR as usize {
+            lfo.advance();
+
+            let value = lfo.value(LfoShape::Random, 0.0);
+            assert!((-1.0..=1.0).contains(&value));
+            // a full swing within one cycle of 100 samples is at most 3 % per sample
+            assert!((value - last).abs() < 0.031, "{last} to {value}");
+
+            last = value;
+        }
+    }
+
+    #[test]
+    fn offset_reads_ahead_of_the_phase() {
+        let mut ahead = Lfo::new(99);
+        let mut lfo = Lfo::new(99);
+        ahead.set_rate(10.0, SR);
+        lfo.set_rate(10.0, SR);
+
+        // 25 samples are a quarter cycle at 10 Hz
+        for _ in 0..25 {
+            ahead.advance();
+        }
+
+        for shape in [LfoShape::Sine, LfoShape::Triangle, LfoShape::Random] {
+            assert!((lfo.value(shape, 0.25) - ahead.value(shape, 0.0)).abs() < 1e-4);
+        }
+    }
+}
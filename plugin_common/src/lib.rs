@@ -0,0 +1,87 @@
+//! Everything the plugins in this workspace would otherwise copy and paste.
+
+use nih_plug::prelude::*;
+use std::ops::RangeInclusive;
+
+pub mod delay_line;
+pub mod filter;
+pub mod lfo;
+pub mod param;
+pub mod stereo;
+pub mod tempo;
+pub mod transient;
+
+pub use stereo::{stereo_frame, Stereo};
+
+pub const VENDOR: &str = "Max Genson";
+pub const URL: &str = "https://www.maxgenson.de";
+pub const EMAIL: &str = "mail@maxgenson.de";
+
+/// Plain stereo in, stereo out
+pub const STEREO_IO_LAYOUTS: &[AudioIOLayout] = &[AudioIOLayout {
+    main_input_channels: NonZeroU32::new(2),
+    main_output_channels: NonZeroU32::new(2),
+    ..AudioIOLayout::const_default()
+}];
+
+/// Stereo in and out with an optional stereo sidechain, hosts pick the first layout they support
+pub const STEREO_SIDECHAIN_IO_LAYOUTS: &[AudioIOLayout] = &[
+    AudioIOLayout {
+        main_input_channels: NonZeroU32::new(2),
+        main_output_channels: NonZeroU32::new(2),
+        aux_input_ports: &[new_nonzero_u32(2)],
+        names: PortNames {
+            aux_inputs: &["Sidechain"],
+            ..PortNames::const_default()
+        },
+        ..AudioIOLayout::const_default()
+    },
+    AudioIOLayout {
+        main_input_channels: NonZeroU32::new(2),
+        main_output_channels: NonZeroU32::new(2),
+        ..AudioIOLayout::const_default()
+    },
+];
+
+/// Emits the identifying `Plugin` constants, use it inside of `impl Plugin for ...`
+///
+/// The version is taken from the Cargo.toml of the plugin that invokes this macro.
+#[macro_export]
+macro_rules! plugin_info {
+    ($name:expr) => {
+        const NAME: &'static str = $name;
+        const VENDOR: &'static str = $crate::VENDOR;
+        const URL: &'static str = $crate::URL;
+        const EMAIL: &'static str = $crate::EMAIL;
+
+        const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    };
+}
+
+/// Logs an error for the host if `sr` is not within `supported`
+///
+/// Meant to be returned from `Plugin::initialize()`.
+pub fn check_sample_rate(sr: f32, supported: RangeInclusive<f32>) -> bool {
+    if supported.contains(&sr) {
+        return true;
+    }
+
+    nih_error!(
+        "Unsupported sample rate of {sr} Hz, expected {} Hz to {} Hz",
+        supported.start(),
+        supported.end()
+    );
+
+    false
+}
+
+/// Average of all channels of the first sidechain at sample `n`, silence if it isn't connected
+#[inline(always)]
+pub fn sidechain_sample(aux: &AuxiliaryBuffers, n: usize) -> f32 {
+    let Some(sidechain) = aux.inputs.first() else {
+        return 0.0;
+    };
+
+    let channels = sidechain.as_slice_immutable();
+    channels.iter().map(|channel| channel[n]).sum::<f32>() / channels.len().max(1) as f32
+}
@@ -0,0 +1,50 @@
+//! Constructors for the kinds of parameters that show up in every plugin.
+
+use nih_plug::prelude::*;
+use nih_plug::util;
+
+/// Time in seconds
+pub fn seconds(name: &str, default: f32, range: FloatRange) -> FloatParam {
+    FloatParam::new(name, default, range)
+        .with_unit(" s")
+        .with_value_to_string(formatters::v2s_f32_rounded(3))
+}
+
+/// Normalized value from 0.0 to 1.0, displayed in percent
+pub fn percentage(name: &str, default: f32, digits: usize) -> FloatParam {
+    FloatParam::new(name, default, FloatRange::Linear { min: 0.0, max: 1.0 })
+        .with_value_to_string(formatters::v2s_f32_percentage(digits))
+        .with_string_to_value(formatters::s2v_f32_percentage())
+        .with_unit(" %")
+}
+
+/// Frequency in Hz, skewed towards the lower end
+pub fn hz(name: &str, default: f32, min: f32, max: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed {
+            min,
+            max,
+            factor: 0.5,
+        },
+    )
+    .with_value_to_string(formatters::v2s_f32_hz_then_khz(2))
+    .with_string_to_value(formatters::s2v_f32_hz_then_khz())
+}
+
+/// Linear gain, displayed and entered in dB
+pub fn gain_db(name: &str, default_db: f32, min_db: f32, max_db: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        util::db_to_gain(default_db),
+        FloatRange::Skewed {
+            min: util::db_to_gain(min_db),
+            max: util::db_to_gain(max_db),
+            factor: FloatRange::gain_skew_factor(min_db, max_db),
+        },
+    )
+    .with_unit(" dB")
+    .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+    .with_string_to_value(formatters::s2v_f32_gain_to_db())
+}
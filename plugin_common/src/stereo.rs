@@ -0,0 +1,105 @@
+/// Holds one of `T` per channel
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stereo<T> {
+    pub l: T,
+    pub r: T,
+}
+
+impl<T> Stereo<T> {
+    pub const fn new(l: T, r: T) -> Stereo<T> {
+        Stereo { l, r }
+    }
+
+    /// Builds both channels with the same constructor
+    pub fn from_fn(mut f: impl FnMut() -> T) -> Stereo<T> {
+        Stereo { l: f(), r: f() }
+    }
+
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Stereo<U> {
+        Stereo {
+            l: f(self.l),
+            r: f(self.r),
+        }
+    }
+
+    pub fn as_mut(&mut self) -> Stereo<&mut T> {
+        Stereo {
+            l: &mut self.l,
+            r: &mut self.r,
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        [&mut self.l, &mut self.r].into_iter()
+    }
+}
+
+impl<T: Clone> Stereo<T> {
+    pub fn splat(value: T) -> Stereo<T> {
+        Stereo {
+            l: value.clone(),
+            r: value,
+        }
+    }
+}
+
+impl<T> From<(T, T)> for Stereo<T> {
+    fn from((l, r): (T, T)) -> Self {
+        Stereo { l, r }
+    }
+}
+
+impl<T> From<Stereo<T>> for (T, T) {
+    fn from(stereo: Stereo<T>) -> Self {
+        (stereo.l, stereo.r)
+    }
+}
+
+/// Splits one frame of `Buffer::iter_samples()` into its left and right sample
+///
+/// Panics if the frame has less than two channels, which can't happen with the stereo layouts.
+pub fn stereo_frame<'a>(
+    samples: impl IntoIterator<Item = &'a mut f32>,
+) -> (&'a mut f32, &'a mut f32) {
+    let mut samples = samples.into_iter();
+    (samples.next().unwrap(), samples.next().unwrap())
+}
+
+/// Returns the (mid, side) representation of a (left, right) frame
+#[inline(always)]
+pub fn to_mid_side((l, r): (f32, f32)) -> (f32, f32) {
+    ((l + r) * 0.5, (l - r) * 0.5)
+}
+
+/// Inverse of [`to_mid_side`]
+#[inline(always)]
+pub fn from_mid_side((mid, side): (f32, f32)) -> (f32, f32) {
+    (mid + side, mid - side)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stereo_frame_splits_channels() {
+        let mut frame = [0.25, -0.5];
+
+        let (left, right) = stereo_frame(frame.iter_mut());
+        (*left, *right) = (*right, *left);
+
+        assert_eq!(frame, [-0.5, 0.25]);
+    }
+
+    #[test]
+    fn mid_side_round_trip() {
+        let frame = (0.75, -0.25);
+
+        assert_eq!(to_mid_side(frame), (0.25, 0.5));
+        assert_eq!(from_mid_side(to_mid_side(frame)), frame);
+    }
+}
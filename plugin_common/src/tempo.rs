@@ -0,0 +1,104 @@
+//! Note values for everything that follows the host's tempo.
+
+use nih_plug::prelude::*;
+
+/// Straight, dotted and triplet notes from 1/64 up to four bars of 4/4
+#[derive(Enum, Clone, Copy, Debug, PartialEq)]
+pub enum NoteDivision {
+    #[name = "1/64 T"]
+    SixtyFourthTriplet,
+    #[name = "1/64"]
+    SixtyFourth,
+    #[name = "1/64 D"]
+    SixtyFourthDotted,
+    #[name = "1/32 T"]
+    ThirtySecondTriplet,
+    #[name = "1/32"]
+    ThirtySecond,
+    #[name = "1/32 D"]
+    ThirtySecondDotted,
+    #[name = "1/16 T"]
+    SixteenthTriplet,
+    #[name = "1/16"]
+    Sixteenth,
+    #[name = "1/16 D"]
+    SixteenthDotted,
+    #[name = "1/8 T"]
+    EighthTriplet,
+    #[name = "1/8"]
+    Eighth,
+    #[name = "1/8 D"]
+    EighthDotted,
+    #[name = "1/4 T"]
+    QuarterTriplet,
+    #[name = "1/4"]
+    Quarter,
+    #[name = "1/4 D"]
+    QuarterDotted,
+    #[name = "1/2 T"]
+    HalfTriplet,
+    #[name = "1/2"]
+    Half,
+    #[name = "1/2 D"]
+    HalfDotted,
+    #[name = "1 Bar"]
+    Bar,
+    #[name = "2 Bars"]
+    TwoBars,
+    #[name = "4 Bars"]
+    FourBars,
+}
+
+impl NoteDivision {
+    /// Length in quarter notes
+    pub fn beats(&self) -> f32 {
+        let triplet = 2.0 / 3.0;
+        let dotted = 1.5;
+
+        match self {
+            NoteDivision::SixtyFourthTriplet => 1.0 / 16.0 * triplet,
+            NoteDivision::SixtyFourth => 1.0 / 16.0,
+            NoteDivision::SixtyFourthDotted => 1.0 / 16.0 * dotted,
+            NoteDivision::ThirtySecondTriplet => 1.0 / 8.0 * triplet,
+            NoteDivision::ThirtySecond => 1.0 / 8.0,
+            NoteDivision::ThirtySecondDotted => 1.0 / 8.0 * dotted,
+            NoteDivision::SixteenthTriplet => 1.0 / 4.0 * triplet,
+            NoteDivision::Sixteenth => 1.0 / 4.0,
+            NoteDivision::SixteenthDotted => 1.0 / 4.0 * dotted,
+            NoteDivision::EighthTriplet => 1.0 / 2.0 * triplet,
+            NoteDivision::Eighth => 1.0 / 2.0,
+            NoteDivision::EighthDotted => 1.0 / 2.0 * dotted,
+            NoteDivision::QuarterTriplet => triplet,
+            NoteDivision::Quarter => 1.0,
+            NoteDivision::QuarterDotted => dotted,
+            NoteDivision::HalfTriplet => 2.0 * triplet,
+            NoteDivision::Half => 2.0,
+            NoteDivision::HalfDotted => 2.0 * dotted,
+            NoteDivision::Bar => 4.0,
+            NoteDivision::TwoBars => 8.0,
+            NoteDivision::FourBars => 16.0,
+        }
+    }
+
+    /// Length in seconds at `tempo` in bpm
+    pub fn seconds(&self, tempo: f64) -> f32 {
+        (self.beats() as f64 * 60.0 / tempo) as f32
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divisions_follow_tempo() {
+        assert_eq!(NoteDivision::Quarter.seconds(120.0), 0.5);
+        assert_eq!(NoteDivision::EighthDotted.seconds(120.0), 0.375);
+        assert_eq!(NoteDivision::FourBars.seconds(60.0), 16.0);
+        assert!((NoteDivision::QuarterTriplet.seconds(90.0) - 4.0 / 9.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,330 @@
+//! Finds the onsets of drum hits, plucks and the like, e.g. to trigger something from a sidechain.
+//! `TransientDetector` looks for peaks that stick out of the recent level, `ThresholdGate` for
+//! a level that crosses a fixed threshold.
+
+/// Follows the peaks closely
+const FAST_ATTACK_MS: f32 = 0.1;
+const FAST_RELEASE_MS: f32 = 20.0;
+
+/// Follows the average level of the last few hits
+const SLOW_ATTACK_MS: f32 = 30.0;
+const SLOW_RELEASE_MS: f32 = 300.0;
+
+/// Nothing quieter than about -60 dBFS counts as a transient
+const NOISE_FLOOR: f32 = 0.001;
+
+/// The peaks have to fall about 3 dB below the threshold again before the next onset
+const HYSTERESIS: f32 = 0.7;
+
+/// Shortest time between two onsets, so flams and rattles only count once
+const LOCKOUT_MS: f32 = 50.0;
+
+#[derive(Default)]
+struct Follower {
+    attack: f32,
+    release: f32,
+    level: f32,
+}
+
+impl Follower {
+    fn set_times(&mut self, attack_ms: f32, release_ms: f32, sr: f32) {
+        self.attack = (-1000.0 / (attack_ms * sr)).exp();
+        self.release = (-1000.0 / (release_ms * sr)).exp();
+    }
+
+    #[inline(always)]
+    fn tick(&mut self, input: f32) -> f32 {
+        let coeff = if input > self.level {
+            self.attack
+        } else {
+            self.release
+        };
+        self.level = input + (self.level - input) * coeff;
+
+        self.level
+    }
+}
+
+/// Compares the peaks of a signal against its recent average level
+#[derive(Default)]
+pub struct TransientDetector {
+    fast: Follower,
+    slow: Follower,
+
+    /// Linear ratio between the peaks and the average
+    threshold: f32,
+    armed: bool,
+
+    lockout: usize,
+    lockout_left: usize,
+}
+
+impl TransientDetector {
+    pub fn new(sr: f32) -> TransientDetector {
+        let mut detector = TransientDetector {
+            threshold: 2.0,
+            armed: true,
+            ..Default::default()
+        };
+        detector.set_sample_rate(sr);
+
+        detector
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    pub fn set_sample_rate(&mut self, sr: f32) {
+        self.fast.set_times(FAST_ATTACK_MS, FAST_RELEASE_MS, sr);
+        self.slow.set_times(SLOW_ATTACK_MS, SLOW_RELEASE_MS, sr);
+        self.lockout = (LOCKOUT_MS / 1000.0 * sr) as usize;
+    }
+
+    /// How far the peaks have to jump above the average level, in dB
+    pub fn set_threshold(&mut self, db: f32) {
+        self.threshold = 10.0_f32.powf(db / 20.0);
+    }
+
+    pub fn reset(&mut self) {
+        self.fast.level = 0.0;
+        self.slow.level = 0.0;
+        self.armed = true;
+        self.lockout_left = 0;
+    }
+
+    /// Returns true on the sample an onset is found
+    #[inline(always)]
+    pub fn tick(&mut self, input: f32) -> bool {
+        let rectified = input.abs();
+        let fast = self.fast.tick(rectified);
+        let slow = self.slow.tick(rectified);
+
+        self.lockout_left = self.lockout_left.saturating_sub(1);
+
+        if !self.armed {
+            self.armed = fast < slow * self.threshold * HYSTERESIS;
+            return false;
+        }
+
+        if self.lockout_left == 0 && fast > NOISE_FLOOR && fast > slow * self.threshold {
+            self.armed = false;
+            self.lockout_left = self.lockout;
+            return true;
+        }
+
+        false
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GateEdge {
+    Open,
+    Close,
+}
+
+/// Opens when the peaks rise above a threshold and closes when they fall below it again
+#[derive(Default)]
+pub struct ThresholdGate {
+    follower: Follower,
+    sr: f32,
+
+    /// Linear levels to open and close at
+    open_level: f32,
+    close_level: f32,
+    open: bool,
+    /// Only a level that comes from below the close level can open the gate, so a crossing
+    /// during the lockout doesn't open it late
+    armed: bool,
+
+    /// Samples the gate stays open after the level falls below the close level
+    hold: usize,
+    hold_left: usize,
+    /// Samples from one opening to the earliest next one
+    lockout: usize,
+    lockout_left: usize,
+}
+
+impl ThresholdGate {
+    pub fn new(sr: f32) -> ThresholdGate {
+        let mut gate = ThresholdGate {
+            armed: true,
+            ..Default::default()
+        };
+        gate.set_sample_rate(sr);
+        gate.set_threshold(-20.0, 6.0);
+
+        gate
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// Resets the hold and lockout times, so they have to be set again
+    pub fn set_sample_rate(&mut self, sr: f32) {
+        self.sr = sr;
+        self.follower.set_times(FAST_ATTACK_MS, FAST_RELEASE_MS, sr);
+        self.hold = 0;
+        self.lockout = 0;
+    }
+
+    /// Opens at `threshold` in dBFS and closes `hysteresis` dB below it
+    pub fn set_threshold(&mut self, threshold: f32, hysteresis: f32) {
+        self.open_level = 10.0_f32.powf(threshold / 20.0);
+        self.close_level = 10.0_f32.powf((threshold - hysteresis.max(0.0)) / 20.0);
+    }
+
+    /// Both in milliseconds
+    pub fn set_times(&mut self, hold_ms: f32, lockout_ms: f32) {
+        self.hold = (hold_ms / 1000.0 * self.sr) as usize;
+        self.lockout = (lockout_ms / 1000.0 * self.sr) as usize;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn reset(&mut self) {
+        self.follower.level = 0.0;
+        self.open = false;
+        self.armed = true;
+        self.hold_left = 0;
+        self.lockout_left = 0;
+    }
+
+    /// Returns the edge on the sample the gate opens or closes
+    #[inline(always)]
+    pub fn tick(&mut self, input: f32) -> Option<GateEdge> {
+        let level = self.follower.tick(input.abs());
+
+        self.lockout_left = self.lockout_left.saturating_sub(1);
+
+        if !self.open {
+            if level <= self.close_level {
+                self.armed = true;
+            } else if level > self.open_level {
+                if self.armed && self.lockout_left == 0 {
+                    self.open = true;
+                    self.hold_left = self.hold;
+                    self.lockout_left = self.lockout;
+                    return Some(GateEdge::Open);
+                }
+                self.armed = false;
+            }
+        } else if level > self.close_level {
+            self.hold_left = self.hold;
+        } else if self.hold_left > 0 {
+            self.hold_left -= 1;
+        } else {
+            self.open = false;
+            self.armed = true;
+            return Some(GateEdge::Close);
+        }
+
+        None
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    /// Decaying 200 Hz bursts, four per second
+    fn hits(n: usize) -> f32 {
+        let t = (n % (SR as usize / 4)) as f32 / SR;
+        (t * 200.0 * std::f32::consts::TAU).sin() * (-t * 30.0).exp()
+    }
+
+    #[test]
+    fn every_hit_triggers_once() {
+        let mut detector = TransientDetector::new(SR);
+        detector.set_threshold(6.0);
+
+        let onsets: Vec<usize> = (0..2 * SR as usize)
+            .filter(|&n| detector.tick(hits(n)))
+            .collect();
+
+        assert_eq!(onsets.len(), 8, "{onsets:?}");
+        for (i, onset) in onsets.iter().enumerate() {
+            // within a millisecond of the hit
+            assert!(onset - i * SR as usize / 4 < 48, "{onsets:?}");
+        }
+    }
+
+    #[test]
+    fn steady_tone_only_triggers_at_the_start() {
+        let mut detector = TransientDetector::new(SR);
+        detector.set_threshold(6.0);
+
+        let onsets = (0..2 * SR as usize)
+            .filter(|&n| detector.tick((n as f32 / SR * 440.0 * std::f32::consts::TAU).sin()))
+            .count();
+
+        assert_eq!(onsets, 1);
+    }
+
+    #[test]
+    fn silence_never_triggers() {
+        let mut detector = TransientDetector::new(SR);
+        detector.set_threshold(0.0);
+
+        assert!(!(0..SR as usize).any(|n| detector.tick(1e-5 * hits(n))));
+    }
+
+    /// Both edges and the sample they're on
+    fn edges(gate: &mut ThresholdGate, input: impl Fn(usize) -> f32) -> Vec<(usize, GateEdge)> {
+        (0..2 * SR as usize)
+            .filter_map(|n| gate.tick(input(n)).map(|edge| (n, edge)))
+            .collect()
+    }
+
+    #[test]
+    fn gate_follows_the_hits() {
+        let mut gate = ThresholdGate::new(SR);
+        gate.set_threshold(-12.0, 6.0);
+
+        let edges = edges(&mut gate, hits);
+
+        assert_eq!(edges.len(), 16, "{edges:?}");
+        for (i, pair) in edges.chunks(2).enumerate() {
+            assert_eq!((pair[0].1, pair[1].1), (GateEdge::Open, GateEdge::Close));
+            assert!(pair[0].0 - i * SR as usize / 4 < 48, "{edges:?}");
+        }
+    }
+
+    #[test]
+    fn hold_keeps_the_gate_open() {
+        let mut gate = ThresholdGate::new(SR);
+        gate.set_threshold(-12.0, 6.0);
+
+        let short = edges(&mut gate, hits)[1].0;
+
+        gate.reset();
+        gate.set_times(100.0, 0.0);
+        let held = edges(&mut gate, hits)[1].0;
+
+        assert_eq!(held - short, 4_800);
+    }
+
+    #[test]
+    fn lockout_skips_early_hits() {
+        let mut gate = ThresholdGate::new(SR);
+        gate.set_threshold(-12.0, 6.0);
+        gate.set_times(0.0, 300.0);
+
+        let opened = edges(&mut gate, hits)
+            .iter()
+            .filter(|(_, edge)| *edge == GateEdge::Open)
+            .count();
+
+        // every second hit falls into the lockout
+        assert_eq!(opened, 4);
+    }
+}
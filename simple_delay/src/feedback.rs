@@ -0,0 +1,121 @@
+//! Everything the repeats pass through before they're written back into the delay lines.
+
+use nih_plug::prelude::*;
+use plugin_common::filter::{Cut, HIGHEST_CUT, LOWEST_CUT};
+
+#[derive(Enum, PartialEq, Clone, Copy)]
+pub enum Saturation {
+    Off,
+    #[name = "Soft Clip"]
+    SoftClip,
+    Tape,
+}
+
+impl Saturation {
+    /// Both curves never exceed 1.0, which keeps feedback above 100 % from blowing up
+    #[inline(always)]
+    fn apply(&self, sample: f32, drive: f32) -> f32 {
+        let driven = sample * drive;
+
+        match self {
+            Saturation::Off => sample,
+            Saturation::SoftClip => driven.tanh() / drive,
+            // softer knee than tanh, so it starts to compress earlier
+            Saturation::Tape => driven / (1.0 + driven * driven).sqrt() / drive,
+        }
+    }
+}
+
+/// Filters and saturates the repeats of one delay line
+pub struct FeedbackPath {
+    lowpass: Cut,
+    highpass: Cut,
+}
+
+impl FeedbackPath {
+    pub fn new() -> FeedbackPath {
+        FeedbackPath {
+            lowpass: Cut::new(),
+            highpass: Cut::new(),
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// `LOWEST_CUT` and `HIGHEST_CUT` switch the filters off, so the repeats stay untouched
+    pub fn set_cutoffs(&mut self, low_cut: f32, high_cut: f32, sr: f32) {
+        self.highpass.set_cutoff(low_cut, low_cut > LOWEST_CUT, sr);
+        self.lowpass
+            .set_cutoff(high_cut, high_cut < HIGHEST_CUT, sr);
+    }
+
+    /// Returns what gets written back, `feedback` is applied before the saturation
+    #[inline(always)]
+    pub fn tick(&mut self, delayed: f32, feedback: f32, saturation: Saturation, drive: f32) -> f32 {
+        let filtered = self.highpass.highpass(self.lowpass.lowpass(delayed));
+
+        saturation.apply(filtered * feedback, drive)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    #[test]
+    fn saturation_bounds_runaway_feedback() {
+        for saturation in [Saturation::SoftClip, Saturation::Tape] {
+            let mut path = FeedbackPath::new();
+            path.set_cutoffs(LOWEST_CUT, HIGHEST_CUT, SR);
+
+            // a loop of a single sample, the worst case for growing repeats
+            let mut delayed = 0.0_f32;
+            for n in 0..SR as usize {
+                let input = if n < 100 { 1.0 } else { 0.0 };
+                delayed = input + path.tick(delayed, 1.2, saturation, 1.0);
+
+                assert!(delayed.abs() <= 2.0, "{delayed} after {n} samples");
+            }
+        }
+    }
+
+    #[test]
+    fn high_cut_darkens_repeats() {
+        let mut path = FeedbackPath::new();
+        path.set_cutoffs(LOWEST_CUT, 1_000.0, SR);
+
+        // alternating samples are a sine at nyquist
+        let peak = (0..1_000)
+            .map(|n| {
+                path.tick(
+                    if n % 2 == 0 { 1.0 } else { -1.0 },
+                    1.0,
+                    Saturation::Off,
+                    1.0,
+                )
+            })
+            .skip(100)
+            .fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+
+        assert!(peak < 0.05, "{peak}");
+    }
+
+    #[test]
+    fn default_cutoffs_leave_the_repeats_untouched() {
+        let mut path = FeedbackPath::new();
+        path.set_cutoffs(LOWEST_CUT, HIGHEST_CUT, SR);
+
+        for n in 0..1_000 {
+            let delayed = (n as f32 * 0.01).sin();
+            assert_eq!(path.tick(delayed, 0.5, Saturation::Off, 1.0), delayed * 0.5);
+        }
+    }
+}
@@ -1,15 +1,58 @@
 use nih_plug::prelude::*;
+use plugin_common::{
+    delay_line::DelayLine,
+    filter::{HIGHEST_CUT, LOWEST_CUT},
+    lfo::{Lfo, LfoShape},
+    param::{gain_db, hz, percentage, seconds},
+    plugin_info,
+    stereo::{from_mid_side, to_mid_side},
+    stereo_frame,
+    tempo::NoteDivision,
+    Stereo, STEREO_IO_LAYOUTS,
+};
 use std::sync::Arc;
-use yanel_dsp::DSPUtility;
 
+mod feedback;
+
+use feedback::{FeedbackPath, Saturation};
+
+/// Short enough for flanging
+const MIN_DELAY_TIME: f32 = 0.001; // seconds
 const MAX_DELAY_TIME: f32 = 10.0; // seconds
 
+/// Enough for tape wow and wide choruses
+const MAX_MOD_DEPTH_MS: f32 = 20.0;
+
+/// Above 100 % the repeats grow, which only stays bounded with saturation
+const MAX_FEEDBACK: f32 = 1.2;
+
+/// Time it takes to move to a new delay time after the tempo or the division changed
+const GLIDE_TIME_MS: f32 = 100.0;
+
+/// For everything else that's smoothed per sample
+const SMOOTHING_TIME_MS: f32 = 20.0;
+
+#[derive(Enum, PartialEq)]
+enum Routing {
+    #[name = "Dual Mono"]
+    DualMono,
+    // the summed input enters on the left and bounces between both sides
+    #[name = "Ping-Pong"]
+    PingPong,
+    #[name = "Cross-Feedback"]
+    CrossFeedback,
+}
+
 struct Delay {
     params: Arc<DelayParams>,
-    l_delay_buffer: Vec<f32>,
-    r_delay_buffer: Vec<f32>,
-    l_delay: yanel_dsp::SimpleDelay,
-    r_delay: yanel_dsp::SimpleDelay,
+    delay: Stereo<DelayLine>,
+    feedback: Stereo<FeedbackPath>,
+    /// Delay time in seconds, either free or synced
+    delay_time: Stereo<Smoother<f32>>,
+    /// Restarting the glide every block would make it depend on the block size
+    delay_time_target: Stereo<f32>,
+    /// Wobbles the read positions
+    lfo: Lfo,
     sr: f32,
 }
 
@@ -21,9 +64,54 @@ struct DelayParams {
     #[id = "R Delay"]
     pub r_delay_time: FloatParam,
 
+    #[id = "Sync"]
+    pub sync: BoolParam,
+
+    #[id = "L Division"]
+    pub l_division: EnumParam<NoteDivision>,
+
+    #[id = "R Division"]
+    pub r_division: EnumParam<NoteDivision>,
+
+    #[id = "Mod Rate"]
+    pub mod_rate: FloatParam,
+
+    #[id = "Mod Depth"]
+    pub mod_depth: FloatParam,
+
+    #[id = "Mod Shape"]
+    pub mod_shape: EnumParam<LfoShape>,
+
+    #[id = "Mod Phase"]
+    pub mod_phase: FloatParam,
+
     #[id = "Feedback"]
     pub feedback: FloatParam,
 
+    #[id = "Low Cut"]
+    pub low_cut: FloatParam,
+
+    #[id = "High Cut"]
+    pub high_cut: FloatParam,
+
+    #[id = "Saturation"]
+    pub saturation: EnumParam<Saturation>,
+
+    #[id = "Drive"]
+    pub drive: FloatParam,
+
+    #[id = "Routing"]
+    pub routing: EnumParam<Routing>,
+
+    #[id = "Cross"]
+    pub cross: FloatParam,
+
+    #[id = "Width"]
+    pub width: FloatParam,
+
+    #[id = "Input Pan"]
+    pub input_pan: FloatParam,
+
     #[id = "Mix"]
     pub mix: FloatParam,
 }
@@ -32,10 +120,11 @@ impl Default for Delay {
     fn default() -> Self {
         Self {
             params: Arc::new(DelayParams::default()),
-            l_delay_buffer: vec![],
-            r_delay_buffer: vec![],
-            l_delay: yanel_dsp::SimpleDelay::init(),
-            r_delay: yanel_dsp::SimpleDelay::init(),
+            delay: Stereo::from_fn(|| DelayLine::new(0)),
+            feedback: Stereo::from_fn(FeedbackPath::new),
+            lfo: Lfo::new(1),
+            delay_time: Stereo::from_fn(|| Smoother::new(SmoothingStyle::Linear(GLIDE_TIME_MS))),
+            delay_time_target: Stereo::splat(0.0),
             sr: 48_000.0,
         }
     }
@@ -44,54 +133,135 @@ impl Default for Delay {
 impl Default for DelayParams {
     fn default() -> Self {
         DelayParams {
-            l_delay_time: FloatParam::new(
+            l_delay_time: seconds(
                 "L Delay",
                 0.4,
                 FloatRange::Skewed {
-                    min: 0.01,
+                    min: MIN_DELAY_TIME,
                     max: MAX_DELAY_TIME,
                     factor: 0.5,
                 },
-            )
-            .with_unit(" s")
-            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+            ),
 
-            r_delay_time: FloatParam::new(
+            r_delay_time: seconds(
                 "R Delay",
                 0.4,
                 FloatRange::Skewed {
-                    min: 0.01,
+                    min: MIN_DELAY_TIME,
                     max: MAX_DELAY_TIME,
                     factor: 0.5,
                 },
+            ),
+
+            sync: BoolParam::new("Sync", false),
+
+            l_division: EnumParam::new("L Division", NoteDivision::Quarter),
+
+            r_division: EnumParam::new("R Division", NoteDivision::Quarter),
+
+            mod_rate: hz("Mod Rate", 0.5, 0.05, 20.0),
+
+            mod_depth: FloatParam::new(
+                "Mod Depth",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: MAX_MOD_DEPTH_MS,
+                    factor: 0.5,
+                },
             )
-            .with_unit(" s")
-            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+            .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS))
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            mod_shape: EnumParam::new("Mod Shape", LfoShape::Sine),
+
+            // the right side runs ahead of the left one
+            mod_phase: FloatParam::new(
+                "Mod Phase",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 180.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS))
+            .with_unit("°")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+
+            feedback: FloatParam::new(
+                "Feedback",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: MAX_FEEDBACK,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS))
+            .with_value_to_string(formatters::v2s_f32_percentage(2))
+            .with_string_to_value(formatters::s2v_f32_percentage())
+            .with_unit(" %"),
+
+            low_cut: hz("Low Cut", LOWEST_CUT, LOWEST_CUT, 2_000.0),
+
+            high_cut: hz("High Cut", HIGHEST_CUT, 500.0, HIGHEST_CUT),
+
+            saturation: EnumParam::new("Saturation", Saturation::Off),
 
-            feedback: FloatParam::new("Feedback", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
+            drive: gain_db("Drive", 0.0, 0.0, 24.0)
+                .with_smoother(SmoothingStyle::Logarithmic(SMOOTHING_TIME_MS)),
 
-            mix: FloatParam::new("Mix", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
-                .with_value_to_string(formatters::v2s_f32_percentage(2))
-                .with_unit(" %"),
+            routing: EnumParam::new("Routing", Routing::DualMono),
+
+            cross: percentage("Cross", 0.5, 0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+
+            width: percentage("Width", 1.0, 0)
+                .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
+
+            input_pan: FloatParam::new(
+                "Input Pan",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS))
+            .with_value_to_string(formatters::v2s_f32_panning())
+            .with_string_to_value(formatters::s2v_f32_panning()),
+
+            mix: percentage("Mix", 0.5, 2).with_smoother(SmoothingStyle::Linear(SMOOTHING_TIME_MS)),
         }
     }
 }
 
-impl Plugin for Delay {
-    const NAME: &'static str = "Simple Delay";
-    const VENDOR: &'static str = "Max Genson";
-    const URL: &'static str = "https://www.maxgenson.de";
-    const EMAIL: &'static str = "mail@maxgenson.de";
+impl DelayParams {
+    /// Falls back to the free time if the host doesn't report a tempo
+    fn delay_time(
+        &self,
+        free: &FloatParam,
+        division: &EnumParam<NoteDivision>,
+        tempo: Option<f64>,
+    ) -> f32 {
+        match tempo {
+            Some(tempo) if self.sync.value() && tempo > 0.0 => {
+                division.value().seconds(tempo).min(MAX_DELAY_TIME)
+            }
+            _ => free.value(),
+        }
+    }
+}
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+/// Gains for `pan` from -1.0 to 1.0, the center leaves both sides untouched
+fn balance(pan: f32) -> (f32, f32) {
+    ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0))
+}
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
-        ..AudioIOLayout::const_default()
-    }];
+impl Plugin for Delay {
+    plugin_info!("Simple Delay");
+
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = STEREO_IO_LAYOUTS;
 
     const MIDI_INPUT: MidiConfig = MidiConfig::None;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -109,13 +279,7 @@ impl Plugin for Delay {
         buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
-        self.sr = buffer_config.sample_rate;
-
-        self.l_delay_buffer = vec![0_f32; MAX_DELAY_TIME.seconds_to_samples(self.sr) as usize];
-        self.r_delay_buffer = vec![0_f32; MAX_DELAY_TIME.seconds_to_samples(self.sr) as usize];
-
-        self.l_delay.set_buffer(self.l_delay_buffer.as_mut_slice());
-        self.r_delay.set_buffer(self.r_delay_buffer.as_mut_slice());
+        self.set_sample_rate(buffer_config.sample_rate);
 
         true
     }
@@ -124,42 +288,38 @@ impl Plugin for Delay {
         &mut self,
         buffer: &mut Buffer,
         _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
         let n_samples = buffer.samples() as u32;
 
-        self.l_delay.set_delay(
-            self.params
-                .l_delay_time
-                .smoothed
-                .next_step(n_samples)
-                .seconds_to_samples(self.sr),
-        );
-        self.r_delay.set_delay(
-            self.params
-                .r_delay_time
-                .smoothed
-                .next_step(n_samples)
-                .seconds_to_samples(self.sr),
-        );
-
-        let feedback = self.params.feedback.smoothed.next_step(n_samples);
+        let tempo = context.transport().tempo;
+        let params = &self.params;
 
-        self.l_delay.set_feedback(feedback);
-        self.r_delay.set_feedback(feedback);
+        // the blocks are split at every parameter change, so this still follows the automation
+        let target = Stereo::new(
+            params.delay_time(&params.l_delay_time, &params.l_division, tempo),
+            params.delay_time(&params.r_delay_time, &params.r_division, tempo),
+        );
+        if target.l != self.delay_time_target.l {
+            self.delay_time.l.set_target(self.sr, target.l);
+        }
+        if target.r != self.delay_time_target.r {
+            self.delay_time.r.set_target(self.sr, target.r);
+        }
+        self.delay_time_target = target;
 
-        let mix = self.params.mix.smoothed.next_step(n_samples);
+        let low_cut = params.low_cut.smoothed.next_step(n_samples);
+        let high_cut = params.high_cut.smoothed.next_step(n_samples);
+        for path in self.feedback.iter_mut() {
+            path.set_cutoffs(low_cut, high_cut, self.sr);
+        }
 
-        self.l_delay.set_dry(1.0 - mix);
-        self.l_delay.set_wet(mix);
-        self.r_delay.set_dry(1.0 - mix);
-        self.r_delay.set_wet(mix);
+        self.lfo
+            .set_rate(params.mod_rate.smoothed.next_step(n_samples), self.sr);
 
         for channel_samples in buffer.iter_samples() {
-            let mut samples = channel_samples.into_iter();
-            let (left, right) = (samples.next().unwrap(), samples.next().unwrap());
-            *left = self.l_delay.tick(*left);
-            *right = self.r_delay.tick(*right);
+            let (left, right) = stereo_frame(channel_samples);
+            (*left, *right) = self.tick((*left, *right));
         }
 
         ProcessStatus::Normal
@@ -168,10 +328,251 @@ impl Plugin for Delay {
     fn deactivate(&mut self) {}
 }
 
+impl Delay {
+    /// Reallocates the delay lines, so don't call this on the audio thread
+    fn set_sample_rate(&mut self, sr: f32) {
+        self.sr = sr;
+        let max_delay_time = MAX_DELAY_TIME + MAX_MOD_DEPTH_MS / 1000.0;
+        self.delay = Stereo::from_fn(|| DelayLine::new((max_delay_time * sr) as usize));
+
+        // without a transport the synced times are unknown, so start from the free times
+        self.delay_time_target = Stereo::new(
+            self.params.l_delay_time.value(),
+            self.params.r_delay_time.value(),
+        );
+        self.delay_time.l.reset(self.delay_time_target.l);
+        self.delay_time.r.reset(self.delay_time_target.r);
+
+        self.lfo.reset();
+    }
+
+    /// Processes one stereo frame, all continuous parameters are smoothed per sample
+    fn tick(&mut self, (left, right): (f32, f32)) -> (f32, f32) {
+        let read_times = self.read_times();
+        let delayed = (
+            self.delay.l.read(read_times.0),
+            self.delay.r.read(read_times.1),
+        );
+
+        let params = &self.params;
+
+        let saturation = params.saturation.value();
+        let drive = params.drive.smoothed.next();
+
+        // without saturation nothing would stop the repeats from growing
+        let feedback = match saturation {
+            Saturation::Off => params.feedback.smoothed.next().min(1.0),
+            _ => params.feedback.smoothed.next(),
+        };
+
+        // how much of the other side's repeats is fed back
+        let routing = params.routing.value();
+        let cross = params.cross.smoothed.next();
+        let cross = match routing {
+            Routing::DualMono => 0.0,
+            Routing::PingPong => 1.0,
+            Routing::CrossFeedback => cross,
+        };
+
+        let input_gain = balance(params.input_pan.smoothed.next());
+        let input = (left * input_gain.0, right * input_gain.1);
+        let input = match routing {
+            Routing::PingPong => ((input.0 + input.1) * 0.5, 0.0),
+            _ => input,
+        };
+
+        let repeats = (
+            self.feedback.l.tick(
+                (1.0 - cross) * delayed.0 + cross * delayed.1,
+                feedback,
+                saturation,
+                drive,
+            ),
+            self.feedback.r.tick(
+                (1.0 - cross) * delayed.1 + cross * delayed.0,
+                feedback,
+                saturation,
+                drive,
+            ),
+        );
+
+        self.delay.l.write(input.0 + repeats.0);
+        self.delay.r.write(input.1 + repeats.1);
+
+        let (mid, side) = to_mid_side(delayed);
+        let wet = from_mid_side((mid, side * params.width.smoothed.next()));
+
+        let mix = params.mix.smoothed.next();
+
+        (
+            (1.0 - mix) * left + mix * wet.0,
+            (1.0 - mix) * right + mix * wet.1,
+        )
+    }
+
+    /// Modulated delay times in samples, advances the glides and the LFO by one sample
+    fn read_times(&mut self) -> (f32, f32) {
+        let params = &self.params;
+
+        let mod_shape = params.mod_shape.value();
+        let mod_depth = params.mod_depth.smoothed.next() / 1000.0;
+        // only ever lengthens the delay, so short times don't get stuck at the minimum
+        let modulation = (
+            (1.0 + self.lfo.value(mod_shape, 0.0)) * 0.5 * mod_depth,
+            (1.0 + self
+                .lfo
+                .value(mod_shape, params.mod_phase.smoothed.next() / 360.0))
+                * 0.5
+                * mod_depth,
+        );
+        self.lfo.advance();
+
+        // updated every sample, so gliding to a new time doesn't zipper
+        (
+            (self.delay_time.l.next() + modulation.0) * self.sr,
+            (self.delay_time.r.next() + modulation.1) * self.sr,
+        )
+    }
+}
+
+impl ClapPlugin for Delay {
+    const CLAP_ID: &'static str = "de.maxgenson.simple-delay";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("Stereo delay with ping-pong and cross-feedback routing");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Delay,
+    ];
+}
+
 impl Vst3Plugin for Delay {
     const VST3_CLASS_ID: [u8; 16] = *b"SimpleDelayMG...";
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
         &[Vst3SubCategory::Fx, Vst3SubCategory::Delay];
 }
 
+nih_export_clap!(Delay);
 nih_export_vst3!(Delay);
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    const SR: f32 = 48_000.0;
+
+    /// Without a host nobody initializes the smoothers, so do it by hand
+    fn reset_smoothers(params: &DelayParams) {
+        for param in [
+            &params.mod_depth,
+            &params.mod_phase,
+            &params.feedback,
+            &params.drive,
+            &params.cross,
+            &params.width,
+            &params.input_pan,
+            &params.mix,
+        ] {
+            param.smoothed.reset(param.value());
+        }
+    }
+
+    #[test]
+    fn automation_ramps_are_continuous() {
+        let mut delay = Delay::default();
+        delay.set_sample_rate(SR);
+        reset_smoothers(&delay.params);
+
+        // jump every automatable value at once, the way a host sends a new automation point
+        delay.delay_time.l.set_target(SR, 0.3);
+        delay.delay_time.r.set_target(SR, 0.5);
+        delay.params.feedback.smoothed.set_target(SR, 0.9);
+        delay.params.mix.smoothed.set_target(SR, 1.0);
+
+        let mut last = (0.0, 0.0);
+        for n in 0..SR as usize {
+            let input = (TAU * 100.0 * n as f32 / SR).sin() * 0.5;
+            let out = delay.tick((input, input));
+
+            // the input's steepest slope is below 0.007 per sample, even a delay time glide at
+            // twice the speed and some feedback stay far below 0.05
+            assert!(
+                (out.0 - last.0).abs() < 0.05 && (out.1 - last.1).abs() < 0.05,
+                "jumped from {last:?} to {out:?} at {n}"
+            );
+            last = out;
+        }
+    }
+
+    #[test]
+    fn modulation_never_sticks_at_the_shortest_delay() {
+        let mut delay = Delay::default();
+        delay.set_sample_rate(SR);
+        reset_smoothers(&delay.params);
+
+        delay.delay_time.l.reset(MIN_DELAY_TIME);
+        delay.delay_time.r.reset(MIN_DELAY_TIME);
+        delay.params.mod_depth.smoothed.reset(5.0);
+        delay.lfo.set_rate(10.0, SR);
+
+        let shortest = MIN_DELAY_TIME * SR;
+        let longest = (MIN_DELAY_TIME + 0.005) * SR;
+
+        let read_times: Vec<f32> = (0..SR as usize).map(|_| delay.read_times().0).collect();
+
+        for (n, &time) in read_times.iter().enumerate() {
+            assert!(
+                time >= shortest - 1e-3 && time <= longest + 1e-3,
+                "read {time} samples back at {n}"
+            );
+        }
+
+        // a sine only spends a few percent of its cycle this close to its trough, a bipolar
+        // modulation clipped at the minimum would spend half of it there
+        let at_bottom = read_times
+            .iter()
+            .filter(|&&time| time < shortest + 0.01 * (longest - shortest))
+            .count();
+        assert!(
+            at_bottom < read_times.len() / 10,
+            "{at_bottom} samples at the bottom"
+        );
+
+        let deepest = read_times.iter().fold(0.0_f32, |a, &b| a.max(b));
+        assert!(deepest > longest - 0.1, "only reached {deepest} samples");
+    }
+
+    #[test]
+    fn mod_phase_changes_glide() {
+        let mut delay = Delay::default();
+        delay.set_sample_rate(SR);
+        reset_smoothers(&delay.params);
+
+        delay.delay_time.r.reset(0.5);
+        delay.params.mod_depth.smoothed.reset(5.0);
+        delay.lfo.set_rate(10.0, SR);
+
+        // a quarter cycle in, where turning the phase around would jump by the full depth
+        let mut last = (0..SR as usize / 40)
+            .map(|_| delay.read_times().1)
+            .last()
+            .unwrap();
+        delay.params.mod_phase.smoothed.set_target(SR, 180.0);
+
+        for n in 0..SR as usize / 20 {
+            let time = delay.read_times().1;
+            assert!(
+                (time - last).abs() < 1.0,
+                "jumped from {last} to {time} at {n}"
+            );
+            last = time;
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use nih_plug::prelude::*;
 use nih_plug_vizia::ViziaState;
+use plugin_common::{plugin_info, STEREO_IO_LAYOUTS};
 
 mod editor;
 mod subwindow;
@@ -55,18 +56,9 @@ impl Default for SimpleGUIParams {
 }
 
 impl Plugin for SimpleGUI {
-    const NAME: &'static str = "Simple GUI";
-    const VENDOR: &'static str = "Max Genson";
-    const URL: &'static str = "https://www.maxgenson.de";
-    const EMAIL: &'static str = "mail@maxgenson.de";
+    plugin_info!("Simple GUI");
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
-
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
-        ..AudioIOLayout::const_default()
-    }];
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = STEREO_IO_LAYOUTS;
 
     const MIDI_INPUT: MidiConfig = MidiConfig::None;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -101,6 +93,19 @@ impl Plugin for SimpleGUI {
     fn deactivate(&mut self) {}
 }
 
+impl ClapPlugin for SimpleGUI {
+    const CLAP_ID: &'static str = "de.maxgenson.simple-gui";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("Example used for UI experiments, does not make or influence any sound");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Utility,
+    ];
+}
+
 impl Vst3Plugin for SimpleGUI {
     const VST3_CLASS_ID: [u8; 16] = *b"SimpleGUIMG.....";
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
@@ -110,4 +115,5 @@ impl Vst3Plugin for SimpleGUI {
     ];
 }
 
+nih_export_clap!(SimpleGUI);
 nih_export_vst3!(SimpleGUI);
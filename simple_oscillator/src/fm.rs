@@ -0,0 +1,103 @@
+//! Phase modulation, where a sine operator or an audio signal shifts the phase of the oscillator.
+
+use nih_plug::prelude::*;
+use std::f32::consts::TAU;
+
+/// Feedback of 100 % in radians, a lot more than this only gives noise
+const MAX_FEEDBACK: f32 = 1.5;
+
+#[derive(Enum, PartialEq, Clone, Copy)]
+pub enum PmSource {
+    /// The modulator operator
+    Internal,
+    /// The main input of the plugin
+    Input,
+    Sidechain,
+}
+
+/// Sine oscillator that can modulate itself
+pub struct Operator {
+    /// From 0.0 to 1.0
+    phase: f32,
+    increment: f32,
+    sr: f32,
+    /// The last two outputs, their average is fed back, which keeps the feedback from ringing
+    /// at nyquist
+    history: [f32; 2],
+}
+
+impl Operator {
+    pub fn new(sr: f32) -> Operator {
+        Operator {
+            phase: 0.0,
+            increment: 0.0,
+            sr,
+            history: [0.0; 2],
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    pub fn set_freq(&mut self, freq: f32) {
+        self.increment = (freq / self.sr).clamp(0.0, 0.5);
+    }
+
+    /// Starts over at the beginning of the cycle
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+        self.history = [0.0; 2];
+    }
+
+    /// `feedback` from 0.0 to 1.0
+    #[inline(always)]
+    pub fn tick(&mut self, feedback: f32) -> f32 {
+        let fed_back = (self.history[0] + self.history[1]) * 0.5 * feedback * MAX_FEEDBACK;
+        let sample = (self.phase * TAU + fed_back).sin();
+
+        self.history = [sample, self.history[0]];
+        self.phase += self.increment;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+
+        sample
+    }
+}
+
+/// Phase offset in cycles for a modulator output, `index` is the peak deviation in radians
+#[inline(always)]
+pub fn phase_offset(modulator: f32, index: f32) -> f32 {
+    modulator * index / TAU
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feedback_adds_harmonics() {
+        let sr = 48_000.0;
+
+        // level of the second harmonic over one second at 100 Hz
+        let second_harmonic = |feedback: f32| {
+            let mut operator = Operator::new(sr);
+            operator.set_freq(100.0);
+
+            let (re, im) = (0..sr as usize).fold((0.0, 0.0), |(re, im), n| {
+                let sample = operator.tick(feedback);
+                let angle = TAU * 200.0 * n as f32 / sr;
+                (re + sample * angle.cos(), im + sample * angle.sin())
+            });
+            (re * re + im * im).sqrt() * 2.0 / sr
+        };
+
+        assert!(second_harmonic(0.0) < 1e-3);
+        assert!(second_harmonic(0.5) > 0.1);
+    }
+}
@@ -0,0 +1,272 @@
+//! Test signals for calibration and measurements. All of them are calibrated the way AES17
+//! defines dBFS: a sine at 0 dBFS peaks at full scale, the noises have the same RMS as that sine
+//! and the impulses peak at full scale.
+
+use nih_plug::prelude::*;
+use std::f32::consts::TAU;
+
+#[derive(Enum, PartialEq, Clone, Copy)]
+pub enum Signal {
+    #[name = "White Noise"]
+    WhiteNoise,
+    /// Falls off by 3 dB per octave
+    #[name = "Pink Noise"]
+    PinkNoise,
+    /// Logarithmic sine sweep, which spends the same time on every octave
+    Sweep,
+    Impulses,
+}
+
+/// Pole and input gain of the one-poles in Paul Kellet's refined pinking filter
+const PINK_POLES: [(f32, f32); 6] = [
+    (0.99886, 0.0555179),
+    (0.99332, 0.0750759),
+    (0.96900, 0.153852),
+    (0.86650, 0.3104856),
+    (0.55000, 0.5329522),
+    (-0.7616, -0.016898),
+];
+/// Gain of the white noise that's added right away and one sample later
+const PINK_DIRECT: (f32, f32) = (0.5362, 0.115926);
+
+/// RMS of the pinking filter for white noise with an RMS of 1.0
+///
+/// That's the square root of the energy of its impulse response, which is computed in closed
+/// form. The one-poles alone contribute `c_j * c_k / (1 - a_j * a_k)` for every pair of them,
+/// the direct gains only affect the first two samples.
+fn pink_rms() -> f32 {
+    let mut energy = 0.0;
+    for (a_j, c_j) in PINK_POLES {
+        for (a_k, c_k) in PINK_POLES {
+            energy += c_j * c_k / (1.0 - a_j * a_k);
+        }
+    }
+
+    let first: f32 = PINK_POLES.iter().map(|(_, c)| c).sum();
+    let second: f32 = PINK_POLES.iter().map(|(a, c)| a * c).sum();
+    energy += 2.0 * first * PINK_DIRECT.0 + PINK_DIRECT.0 * PINK_DIRECT.0;
+    energy += 2.0 * second * PINK_DIRECT.1 + PINK_DIRECT.1 * PINK_DIRECT.1;
+
+    energy.sqrt()
+}
+
+/// Uniform noise from -1.0 to 1.0 has an RMS of 1/sqrt(3), this brings it to the RMS of a sine
+const WHITE_GAIN: f32 = 1.224_744_9; // sqrt(3) / sqrt(2)
+
+pub struct Generator {
+    sr: f32,
+    signal: Signal,
+    rng_state: u32,
+
+    pink: [f32; 6],
+    /// Previous white noise sample, for the delayed direct gain of the pinking filter
+    pink_delayed: f32,
+    pink_gain: f32,
+
+    /// Start and end frequency in Hz
+    sweep_range: (f32, f32),
+    /// Length of one sweep in samples
+    sweep_length: u32,
+    sweep_looped: bool,
+    /// Samples since the start of the sweep
+    sweep_elapsed: u32,
+    sweep_phase: f32,
+
+    /// Samples between two impulses, which don't need to be whole
+    impulse_period: f32,
+    /// Samples until the next impulse
+    impulse_countdown: f32,
+}
+
+impl Generator {
+    pub fn new(sr: f32) -> Generator {
+        Generator {
+            sr,
+            signal: Signal::WhiteNoise,
+            rng_state: 1,
+
+            pink: [0.0; 6],
+            pink_delayed: 0.0,
+            pink_gain: WHITE_GAIN / pink_rms(),
+
+            sweep_range: (20.0, 20_000.0),
+            sweep_length: sr as u32,
+            sweep_looped: true,
+            sweep_elapsed: 0,
+            sweep_phase: 0.0,
+
+            impulse_period: sr,
+            impulse_countdown: 0.0,
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// Switching to another signal starts it over
+    pub fn set_signal(&mut self, signal: Signal) {
+        if signal != self.signal {
+            self.signal = signal;
+            self.restart();
+        }
+    }
+
+    /// Frequencies in Hz, `duration` in seconds. A sweep that's not `looped` stays silent after
+    /// its end, until it's restarted.
+    pub fn set_sweep(&mut self, start: f32, end: f32, duration: f32, looped: bool) {
+        self.sweep_range = (start, end);
+        self.sweep_length = ((duration * self.sr) as u32).max(1);
+        self.sweep_looped = looped;
+    }
+
+    /// Impulses per second
+    pub fn set_impulse_rate(&mut self, rate: f32) {
+        self.impulse_period = self.sr / rate;
+    }
+
+    /// Starts the sweep and the impulses from the beginning
+    pub fn restart(&mut self) {
+        self.sweep_elapsed = 0;
+        self.sweep_phase = 0.0;
+        self.impulse_countdown = 0.0;
+    }
+
+    /// At 0 dBFS
+    #[inline(always)]
+    pub fn tick(&mut self) -> f32 {
+        match self.signal {
+            Signal::WhiteNoise => self.next_random() * WHITE_GAIN,
+            Signal::PinkNoise => {
+                let white = self.next_random();
+
+                for (state, (pole, gain)) in self.pink.iter_mut().zip(PINK_POLES) {
+                    *state = pole * *state + gain * white;
+                }
+
+                let pink = self.pink.iter().sum::<f32>()
+                    + white * PINK_DIRECT.0
+                    + self.pink_delayed * PINK_DIRECT.1;
+                self.pink_delayed = white;
+
+                pink * self.pink_gain
+            }
+            Signal::Sweep => self.sweep(),
+            Signal::Impulses => {
+                let impulse = if self.impulse_countdown <= 0.0 {
+                    self.impulse_countdown += self.impulse_period;
+                    1.0
+                } else {
+                    0.0
+                };
+                self.impulse_countdown -= 1.0;
+
+                impulse
+            }
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Private Functions
+    ///////////////////////////////////////////////////////////////////////////////
+
+    #[inline(always)]
+    fn sweep(&mut self) -> f32 {
+        if self.sweep_elapsed >= self.sweep_length {
+            if !self.sweep_looped {
+                return 0.0;
+            }
+            self.restart();
+        }
+
+        let (start, end) = self.sweep_range;
+        let progress = self.sweep_elapsed as f32 / self.sweep_length as f32;
+        let freq = start * (end / start).powf(progress);
+
+        let sample = (self.sweep_phase * TAU).sin();
+
+        self.sweep_phase = (self.sweep_phase + freq / self.sr).fract();
+        self.sweep_elapsed += 1;
+
+        sample
+    }
+
+    /// From -1.0 to 1.0
+    fn next_random(&mut self) -> f32 {
+        // xorshift32
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+
+        self.rng_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    const SR: f32 = 48_000.0;
+
+    fn render(generator: &mut Generator, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SR) as usize)
+            .map(|_| generator.tick())
+            .collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn noise_has_the_rms_of_a_sine() {
+        for signal in [Signal::WhiteNoise, Signal::PinkNoise] {
+            let mut generator = Generator::new(SR);
+            generator.set_signal(signal);
+
+            let level = 20.0 * (rms(&render(&mut generator, 10.0)) / FRAC_1_SQRT_2).log10();
+            assert!(level.abs() < 0.1, "{level} dB");
+        }
+    }
+
+    #[test]
+    fn sweep_covers_its_range_once() {
+        let (start, end, duration) = (100.0, 1_000.0, 1.0);
+
+        let mut generator = Generator::new(SR);
+        generator.set_signal(Signal::Sweep);
+        generator.set_sweep(start, end, duration, false);
+
+        let samples = render(&mut generator, 2.0);
+        let crossings = samples
+            .windows(2)
+            .filter(|pair| (pair[0] < 0.0) != (pair[1] < 0.0))
+            .count() as f32;
+
+        // two per cycle, the cycles are the integral of the exponential frequency
+        let expected = 2.0 * (end - start) * duration / (end / start).ln();
+        assert!((crossings - expected).abs() < 3.0, "{crossings} crossings");
+
+        assert!(samples[..SR as usize].iter().any(|x| *x > 0.999));
+        assert!(samples[SR as usize..].iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn impulses_follow_their_rate() {
+        let mut generator = Generator::new(SR);
+        generator.set_signal(Signal::Impulses);
+        generator.set_impulse_rate(10.0);
+
+        let samples = render(&mut generator, 1.0);
+        let impulses: Vec<usize> = (0..samples.len()).filter(|&n| samples[n] != 0.0).collect();
+
+        assert_eq!(impulses.len(), 10);
+        assert_eq!(impulses[..2], [0, 4_800]);
+        assert!(impulses.iter().all(|&n| samples[n] == 1.0));
+    }
+}
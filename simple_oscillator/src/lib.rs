@@ -1,12 +1,23 @@
 use nih_plug::prelude::*;
 use nih_plug::util;
+use plugin_common::{
+    param::{hz, percentage, seconds},
+    plugin_info, sidechain_sample, stereo_frame, STEREO_SIDECHAIN_IO_LAYOUTS,
+};
 use std::sync::Arc;
 
-use embedded_audio_tools::{FunctionalOscillator, PhaseAccumulator, SoftPhaseAccumulator};
+mod fm;
+mod generator;
+mod oscillator;
+mod unison;
+mod voice;
 
-use Waveform::*;
+use fm::{phase_offset, Operator, PmSource};
+use generator::{Generator, Signal};
+use unison::{StartPhase, Unison, MAX_UNISON};
+use voice::{VoicePool, MAX_VOICES};
 
-#[derive(Enum, PartialEq)]
+#[derive(Enum, PartialEq, Clone, Copy)]
 enum Waveform {
     Sine,
     Rectangle,
@@ -14,28 +25,149 @@ enum Waveform {
     Triangle,
 }
 
+#[derive(Enum, PartialEq)]
+enum Mode {
+    /// Played by MIDI notes
+    #[name = "MIDI"]
+    Midi,
+    /// A single tone at Freq that never stops
+    Drone,
+    /// Test signals at a calibrated level
+    Generator,
+}
+
 struct Oscillator {
     params: Arc<OscillatorParams>,
-    osc: FunctionalOscillator<SoftPhaseAccumulator>,
+    osc: Unison,
+    /// Modulates `osc` in drone mode, every voice has its own one
+    modulator: Operator,
+    voices: VoicePool,
+    generator: Generator,
+    /// Whether the transport was playing during the last buffer, to find where it starts
+    playing: bool,
 }
 
 #[derive(Params)]
 struct OscillatorParams {
+    #[id = "Mode"]
+    pub mode: EnumParam<Mode>,
+
     #[id = "Wave"]
     pub wave: EnumParam<Waveform>,
 
+    /// Turning it off gives the naive waveforms, which alias at high frequencies
+    #[id = "Anti-Aliasing"]
+    pub anti_aliasing: BoolParam,
+
+    /// Drone only
     #[id = "Freq"]
     pub freq: FloatParam,
 
+    /// Number of detuned copies of the oscillator, per voice in MIDI mode
+    #[id = "Unison"]
+    pub unison: IntParam,
+
+    /// In cents, between the outermost copies and the center
+    #[id = "Detune"]
+    pub detune: FloatParam,
+
+    /// How far the copies are spread across the stereo field
+    #[id = "Width"]
+    pub width: FloatParam,
+
+    /// Phase of the copies at the start of a note
+    #[id = "Start Phase"]
+    pub start_phase: EnumParam<StartPhase>,
+
+    /// What modulates the phase of the oscillator
+    #[id = "PM Source"]
+    pub pm_source: EnumParam<PmSource>,
+
+    /// Whole part of the frequency ratio between the modulator and the oscillator
+    #[id = "FM Coarse"]
+    pub fm_coarse: IntParam,
+
+    /// Added to the coarse ratio
+    #[id = "FM Fine"]
+    pub fm_fine: FloatParam,
+
+    /// Peak phase deviation in radians, for every source
+    #[id = "FM Index"]
+    pub fm_index: FloatParam,
+
+    /// How much the modulator modulates itself
+    #[id = "FM Feedback"]
+    pub fm_feedback: FloatParam,
+
+    #[id = "Voices"]
+    pub voices: IntParam,
+
+    /// In semitones
+    #[id = "Bend Range"]
+    pub bend_range: IntParam,
+
+    /// How much the velocity affects the amplitude
+    #[id = "Velocity"]
+    pub velocity: FloatParam,
+
+    #[id = "Attack"]
+    pub attack: FloatParam,
+
+    #[id = "Decay"]
+    pub decay: FloatParam,
+
+    #[id = "Sustain"]
+    pub sustain: FloatParam,
+
+    #[id = "Release"]
+    pub release: FloatParam,
+
+    #[id = "Signal"]
+    pub signal: EnumParam<Signal>,
+
+    /// Generator only, in dBFS. Unlike Gain it can be set to exact values like -18 dBFS.
+    #[id = "Level"]
+    pub level: FloatParam,
+
+    #[id = "Sweep Start"]
+    pub sweep_start: FloatParam,
+
+    #[id = "Sweep End"]
+    pub sweep_end: FloatParam,
+
+    #[id = "Sweep Time"]
+    pub sweep_time: FloatParam,
+
+    /// Otherwise the sweep plays once and stays silent until it's restarted
+    #[id = "Sweep Loop"]
+    pub sweep_loop: BoolParam,
+
+    /// Restarts the sweep and the impulses whenever the transport starts playing
+    #[id = "Sync"]
+    pub sync: BoolParam,
+
+    /// Impulses per second
+    #[id = "Impulse Rate"]
+    pub impulse_rate: FloatParam,
+
     #[id = "Gain"]
     pub gain: FloatParam,
+
+    /// Level of the input that's mixed into the output, so the plugin can insert a tone into a
+    /// track
+    #[id = "Input"]
+    pub input: FloatParam,
 }
 
 impl Default for Oscillator {
     fn default() -> Self {
         Self {
             params: Arc::new(OscillatorParams::default()),
-            osc: FunctionalOscillator::new(SoftPhaseAccumulator::new(100.0, 48_000.0)),
+            osc: Unison::new(100.0, 48_000.0, 0),
+            modulator: Operator::new(48_000.0),
+            voices: VoicePool::new(48_000.0),
+            generator: Generator::new(48_000.0),
+            playing: false,
         }
     }
 }
@@ -43,17 +175,133 @@ impl Default for Oscillator {
 impl Default for OscillatorParams {
     fn default() -> Self {
         Self {
+            mode: EnumParam::new("Mode", Mode::Drone),
+
             wave: EnumParam::new("Wave", Waveform::Sine),
-            freq: FloatParam::new(
-                "Freq",
-                100.0,
+            anti_aliasing: BoolParam::new("Anti-Aliasing", true),
+            freq: hz("Freq", 100.0, 20.0, 20_000.0),
+
+            unison: IntParam::new(
+                "Unison",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_UNISON as i32,
+                },
+            ),
+
+            detune: FloatParam::new(
+                "Detune",
+                15.0,
                 FloatRange::Skewed {
-                    min: 20.0,
-                    max: 20_000.0,
+                    min: 0.0,
+                    max: 100.0,
                     factor: 0.5,
                 },
             )
-            .with_value_to_string(formatters::v2s_f32_hz_then_khz(2)),
+            .with_unit(" ct")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            width: percentage("Width", 1.0, 0),
+            start_phase: EnumParam::new("Start Phase", StartPhase::Fixed),
+
+            pm_source: EnumParam::new("PM Source", PmSource::Internal),
+
+            fm_coarse: IntParam::new("FM Coarse", 1, IntRange::Linear { min: 0, max: 16 }),
+
+            fm_fine: FloatParam::new("FM Fine", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::v2s_f32_rounded(3)),
+
+            fm_index: FloatParam::new(
+                "FM Index",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 10.0,
+                    factor: 0.5,
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+
+            fm_feedback: percentage("FM Feedback", 0.0, 0),
+
+            voices: IntParam::new(
+                "Voices",
+                8,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_VOICES as i32,
+                },
+            ),
+
+            bend_range: IntParam::new("Bend Range", 2, IntRange::Linear { min: 0, max: 24 })
+                .with_unit(" st"),
+
+            velocity: percentage("Velocity", 1.0, 0),
+
+            attack: seconds(
+                "Attack",
+                0.01,
+                FloatRange::Skewed {
+                    min: 0.001,
+                    max: 5.0,
+                    factor: 0.3,
+                },
+            ),
+
+            decay: seconds(
+                "Decay",
+                0.3,
+                FloatRange::Skewed {
+                    min: 0.001,
+                    max: 5.0,
+                    factor: 0.3,
+                },
+            ),
+
+            sustain: percentage("Sustain", 0.7, 0),
+
+            release: seconds(
+                "Release",
+                0.3,
+                FloatRange::Skewed {
+                    min: 0.001,
+                    max: 10.0,
+                    factor: 0.3,
+                },
+            ),
+
+            signal: EnumParam::new("Signal", Signal::PinkNoise),
+
+            level: FloatParam::new(
+                "Level",
+                -18.0,
+                FloatRange::Linear {
+                    min: -80.0,
+                    max: 0.0,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_step_size(0.1)
+            .with_unit(" dBFS"),
+
+            sweep_start: hz("Sweep Start", 20.0, 20.0, 20_000.0),
+            sweep_end: hz("Sweep End", 20_000.0, 20.0, 20_000.0),
+
+            sweep_time: seconds(
+                "Sweep Time",
+                10.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 60.0,
+                    factor: 0.3,
+                },
+            ),
+
+            sweep_loop: BoolParam::new("Sweep Loop", true),
+            sync: BoolParam::new("Sync", false),
+
+            impulse_rate: hz("Impulse Rate", 1.0, 0.1, 100.0),
 
             gain: FloatParam::new(
                 "Gain",
@@ -67,25 +315,31 @@ impl Default for OscillatorParams {
             .with_unit(" dB")
             .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
+
+            input: FloatParam::new(
+                "Input",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1.0,
+                    factor: 0.5,
+                },
+            )
+            .with_smoother(SmoothingStyle::Linear(10.0))
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db()),
         }
     }
 }
 
 impl Plugin for Oscillator {
-    const NAME: &'static str = "Simple Oscillator";
-    const VENDOR: &'static str = "Max Genson";
-    const URL: &'static str = "https://www.maxgenson.de";
-    const EMAIL: &'static str = "mail@maxgenson.de";
+    plugin_info!("Simple Oscillator");
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = STEREO_SIDECHAIN_IO_LAYOUTS;
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
-        ..AudioIOLayout::const_default()
-    }];
-
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    // Basic would leave out the pitch bend
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
     type SysExMessage = ();
@@ -95,22 +349,133 @@ impl Plugin for Oscillator {
         self.params.clone()
     }
 
+    fn initialize(
+        &mut self,
+        _audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        let sr = buffer_config.sample_rate;
+
+        self.osc = Unison::new(100.0, sr, 0);
+        self.modulator = Operator::new(sr);
+        self.voices = VoicePool::new(sr);
+        self.generator = Generator::new(sr);
+
+        true
+    }
+
+    fn reset(&mut self) {
+        self.osc.restart(self.params.start_phase.value());
+        self.modulator.reset();
+        self.voices.all_notes_off();
+        self.generator.restart();
+    }
+
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
-        for channel_samples in buffer.iter_samples() {
-            self.osc
-                .set_freq_unchecked(self.params.freq.modulated_plain_value());
-            self.osc.set_wave(enum_parser(self.params.wave.value()));
+        let params = &self.params;
+
+        self.voices.set_envelope(
+            params.attack.value(),
+            params.decay.value(),
+            params.sustain.value(),
+            params.release.value(),
+        );
+        self.voices
+            .set_wave(params.wave.value(), params.anti_aliasing.value());
+
+        let (unison, detune, width) = (
+            params.unison.value() as usize,
+            params.detune.value(),
+            params.width.value(),
+        );
+        self.voices
+            .set_unison(unison, detune, width, params.start_phase.value());
+        self.osc.set_unison(unison, detune, width);
+
+        let ratio = params.fm_coarse.value() as f32 + params.fm_fine.value();
+        let (index, feedback) = (params.fm_index.value(), params.fm_feedback.value());
+        self.voices.set_fm(ratio, index, feedback);
+
+        self.generator.set_signal(params.signal.value());
+        self.generator.set_sweep(
+            params.sweep_start.value(),
+            params.sweep_end.value(),
+            params.sweep_time.value(),
+            params.sweep_loop.value(),
+        );
+        self.generator.set_impulse_rate(params.impulse_rate.value());
+
+        let playing = context.transport().playing;
+        if playing && !self.playing && params.sync.value() {
+            self.generator.restart();
+        }
+        self.playing = playing;
 
-            let next = self.osc.next() * self.params.gain.smoothed.next();
+        let mut next_event = context.next_event();
 
-            for sample in channel_samples {
-                *sample = next;
+        for (n, channel_samples) in buffer.iter_samples().enumerate() {
+            while let Some(event) = next_event {
+                if event.timing() > n as u32 {
+                    break;
+                }
+
+                match event {
+                    NoteEvent::NoteOn { note, velocity, .. } => {
+                        let amount = params.velocity.value();
+                        self.voices.note_on(
+                            note,
+                            1.0 - amount + amount * velocity,
+                            params.voices.value() as usize,
+                        );
+                    }
+                    NoteEvent::NoteOff { note, .. } => self.voices.note_off(note),
+                    NoteEvent::MidiPitchBend { value, .. } => self
+                        .voices
+                        .set_bend((value * 2.0 - 1.0) * params.bend_range.value() as f32),
+                    _ => (),
+                }
+
+                next_event = context.next_event();
             }
+
+            let (gain, input) = (params.gain.smoothed.next(), params.input.smoothed.next());
+            let level = util::db_to_gain(params.level.smoothed.next());
+
+            let (left, right) = stereo_frame(channel_samples);
+
+            let external = match params.pm_source.value() {
+                PmSource::Internal => None,
+                PmSource::Input => Some((*left + *right) * 0.5),
+                PmSource::Sidechain => Some(sidechain_sample(aux, n)),
+            };
+
+            let (osc, gain) = match params.mode.value() {
+                Mode::Midi => (self.voices.tick(external), gain),
+                Mode::Drone => {
+                    let freq = params.freq.modulated_plain_value();
+                    self.osc.set_freq(freq);
+                    self.osc
+                        .set_wave(params.wave.value(), params.anti_aliasing.value());
+                    self.modulator.set_freq(freq * ratio);
+
+                    let modulator = external.unwrap_or_else(|| self.modulator.tick(feedback));
+                    (self.osc.tick(phase_offset(modulator, index)), gain)
+                }
+                // the calibrated level replaces the gain
+                Mode::Generator => {
+                    let sample = self.generator.tick();
+                    ((sample, sample), level)
+                }
+            };
+
+            *left = osc.0 * gain + *left * input;
+            *right = osc.1 * gain + *right * input;
         }
 
         ProcessStatus::Normal
@@ -119,18 +484,23 @@ impl Plugin for Oscillator {
     fn deactivate(&mut self) {}
 }
 
+impl ClapPlugin for Oscillator {
+    const CLAP_ID: &'static str = "de.maxgenson.simple-oscillator";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("Basic oscillator with the common waveforms");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::Instrument,
+        ClapFeature::Synthesizer,
+        ClapFeature::Stereo,
+    ];
+}
+
 impl Vst3Plugin for Oscillator {
     const VST3_CLASS_ID: [u8; 16] = *b"SimpleOscillator";
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Synth];
 }
 
+nih_export_clap!(Oscillator);
 nih_export_vst3!(Oscillator);
-
-fn enum_parser(nih_enum: Waveform) -> embedded_audio_tools::oscillator::Waveform {
-    match nih_enum {
-        Sine => embedded_audio_tools::oscillator::Waveform::Sine,
-        Rectangle => embedded_audio_tools::oscillator::Waveform::Rectangle,
-        Sawtooth => embedded_audio_tools::oscillator::Waveform::Sawtooth,
-        Triangle => embedded_audio_tools::oscillator::Waveform::Triangle,
-    }
-}
@@ -0,0 +1,304 @@
+//! Oscillator with band-limited waveforms. The steps of the rectangle and sawtooth are smoothed
+//! with PolyBLEPs, the corners of the triangle with PolyBLAMPs. At high frequencies, where only a
+//! few harmonics fit below nyquist, the waveforms are summed from their harmonics instead.
+
+use crate::Waveform;
+use std::f32::consts::{FRAC_2_PI, PI, TAU};
+
+/// Above this increment the steps come so close together that the PolyBLEPs alias audibly, so
+/// the harmonics take over. At most 11 of them fit below nyquist there.
+const ADDITIVE_START: f32 = 1.0 / 24.0;
+/// Both are blended up to this increment, so sweeping through doesn't change the sound abruptly
+const ADDITIVE_FULL: f32 = 1.0 / 16.0;
+/// Harmonics fade out over this band below nyquist, relative to the sample rate, so they don't
+/// pop in and out while the frequency changes
+const HARMONIC_ROLLOFF: f32 = 0.05;
+
+/// Integral of a cubic B-spline, a step from 0.0 to 1.0 that's smeared from -2.0 to 2.0 samples
+#[inline(always)]
+fn smooth_step(x: f32) -> f32 {
+    if x <= -2.0 {
+        0.0
+    } else if x <= -1.0 {
+        (2.0 + x).powi(4) / 24.0
+    } else if x <= 0.0 {
+        0.5 + 2.0 * x / 3.0 - x.powi(3) / 3.0 - x.powi(4) / 8.0
+    } else if x < 1.0 {
+        0.5 + 2.0 * x / 3.0 - x.powi(3) / 3.0 + x.powi(4) / 8.0
+    } else if x < 2.0 {
+        1.0 - (2.0 - x).powi(4) / 24.0
+    } else {
+        1.0
+    }
+}
+
+/// Difference between a band-limited and a naive step from -1.0 to 1.0 at the start of the
+/// cycle, spread over two samples on each side
+#[inline(always)]
+fn poly_blep(phase: f32, dt: f32) -> f32 {
+    // samples since the last step and until the next one
+    let (after, before) = (phase / dt, (phase - 1.0) / dt);
+    let mut residual = 0.0;
+
+    if after < 2.0 {
+        residual += 2.0 * (smooth_step(after) - 1.0);
+    }
+    if before > -2.0 {
+        residual += 2.0 * smooth_step(before);
+    }
+
+    residual
+}
+
+/// Difference between a band-limited and a naive corner at the start of the cycle, where the
+/// slope grows by 2.0 per sample. Two samples are enough here, since the harmonics of corners
+/// fall off a lot faster than the ones of steps.
+#[inline(always)]
+fn poly_blamp(phase: f32, dt: f32) -> f32 {
+    if phase < dt {
+        let t = phase / dt - 1.0;
+        -t * t * t / 3.0
+    } else if phase > 1.0 - dt {
+        let t = (phase - 1.0) / dt + 1.0;
+        t * t * t / 3.0
+    } else {
+        0.0
+    }
+}
+
+/// Sum of all harmonics of the waveform below nyquist
+#[inline(always)]
+fn additive(wave: Waveform, phase: f32, dt: f32) -> f32 {
+    let (sin, cos) = (phase * TAU).sin_cos();
+    // sin and cos of the k-th harmonic, rotated further by one harmonic each time
+    let (mut s, mut c) = (sin, cos);
+    let mut sum = 0.0;
+
+    let mut k = 1;
+    while k as f32 * dt < 0.5 {
+        // the fundamental never fades, otherwise the level would drop right below nyquist
+        let gain = if k == 1 {
+            1.0
+        } else {
+            ((0.5 - k as f32 * dt) / HARMONIC_ROLLOFF).min(1.0)
+        };
+        let n = k as f32;
+
+        sum += gain
+            * match wave {
+                Waveform::Sine if k == 1 => s,
+                Waveform::Sawtooth => -FRAC_2_PI * s / n,
+                Waveform::Rectangle if k % 2 == 1 => 2.0 * FRAC_2_PI * s / n,
+                Waveform::Triangle if k % 2 == 1 => -8.0 / (PI * PI) * c / (n * n),
+                _ => 0.0,
+            };
+
+        (s, c) = (s * cos + c * sin, c * cos - s * sin);
+        k += 1;
+    }
+
+    sum
+}
+
+pub struct BlepOscillator {
+    /// From 0.0 to 1.0
+    phase: f32,
+    increment: f32,
+    sr: f32,
+
+    wave: Waveform,
+    band_limited: bool,
+}
+
+impl BlepOscillator {
+    pub fn new(freq: f32, sr: f32) -> BlepOscillator {
+        let mut osc = BlepOscillator {
+            phase: 0.0,
+            increment: 0.0,
+            sr,
+
+            wave: Waveform::Sine,
+            band_limited: true,
+        };
+        osc.set_freq(freq);
+
+        osc
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// Stops at nyquist
+    pub fn set_freq(&mut self, freq: f32) {
+        self.increment = (freq / self.sr).clamp(0.0, 0.5);
+    }
+
+    /// The naive waveforms alias, but keep their corners exactly where they are
+    pub fn set_wave(&mut self, wave: Waveform, band_limited: bool) {
+        self.wave = wave;
+        self.band_limited = band_limited;
+    }
+
+    /// Jumps to `phase`, from 0.0 to 1.0
+    pub fn reset(&mut self, phase: f32) {
+        self.phase = phase.fract();
+    }
+
+    /// `offset` shifts the phase of this sample in cycles, for phase modulation. The band-limiting
+    /// still assumes the unmodulated frequency, so deep modulation aliases a bit more.
+    #[inline(always)]
+    pub fn tick(&mut self, offset: f32) -> f32 {
+        let dt = self.increment;
+        let phase = (self.phase + offset).rem_euclid(1.0);
+        // rem_euclid rounds tiny negative values up to 1.0
+        let phase = if phase < 1.0 { phase } else { 0.0 };
+        // where the second half of the cycle starts
+        let half = (phase + 0.5).fract();
+
+        let mut sample = match self.wave {
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Rectangle => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        };
+
+        if self.band_limited {
+            sample += match self.wave {
+                Waveform::Sine => 0.0,
+                Waveform::Rectangle => poly_blep(phase, dt) - poly_blep(half, dt),
+                Waveform::Sawtooth => -poly_blep(phase, dt),
+                // the slope changes by 8.0 per cycle at both corners
+                Waveform::Triangle => 4.0 * dt * (poly_blamp(phase, dt) - poly_blamp(half, dt)),
+            };
+
+            if self.wave != Waveform::Sine && dt > ADDITIVE_START {
+                let blend = ((dt - ADDITIVE_START) / (ADDITIVE_FULL - ADDITIVE_START)).min(1.0);
+                sample += blend * (additive(self.wave, phase, dt) - sample);
+            }
+        }
+
+        self.phase += dt;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+
+        sample
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    /// Level of everything that's not a harmonic of `freq` relative to the harmonics, in dB
+    ///
+    /// Renders exactly one second, so every harmonic falls on a single DFT bin.
+    fn inharmonic_level(wave: Waveform, band_limited: bool, freq: usize) -> f32 {
+        let mut osc = BlepOscillator::new(freq as f32, SR);
+        osc.set_wave(wave, band_limited);
+
+        let samples: Vec<f64> = (0..SR as usize).map(|_| osc.tick(0.0) as f64).collect();
+        let len = samples.len() as f64;
+
+        let mean = samples.iter().sum::<f64>() / len;
+        let total = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / len;
+
+        let harmonics: f64 = (1..)
+            .map(|k| k * freq)
+            .take_while(|&bin| bin < SR as usize / 2)
+            .map(|bin| {
+                let (re, im) = samples
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (n, x)| {
+                        let angle = std::f64::consts::TAU * (bin * n) as f64 / len;
+                        (re + x * angle.cos(), im - x * angle.sin())
+                    });
+                2.0 * (re * re + im * im) / (len * len)
+            })
+            .sum();
+
+        (10.0 * ((total - harmonics).max(1e-20) / harmonics).log10()) as f32
+    }
+
+    #[test]
+    fn high_sawtooth_barely_aliases() {
+        // PolyBLEPs only, blended and summed from the harmonics, up to the top of the range
+        for freq in [1_900, 2_500, 4_700, 15_000, 19_000] {
+            let naive = inharmonic_level(Waveform::Sawtooth, false, freq);
+            let band_limited = inharmonic_level(Waveform::Sawtooth, true, freq);
+
+            assert!(band_limited < -35.0, "{band_limited} dB at {freq} Hz");
+            assert!(
+                band_limited < naive - 20.0,
+                "{band_limited} dB against {naive} dB at {freq} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn every_waveform_aliases_less() {
+        for wave in [Waveform::Rectangle, Waveform::Triangle] {
+            for freq in [4_700, 15_000, 19_000] {
+                let naive = inharmonic_level(wave, false, freq);
+                let band_limited = inharmonic_level(wave, true, freq);
+
+                assert!(
+                    band_limited < naive - 15.0,
+                    "{band_limited} dB against {naive} dB at {freq} Hz"
+                );
+            }
+        }
+    }
+
+    /// RMS level in dB
+    fn level(wave: Waveform, freq: f32) -> f32 {
+        let mut osc = BlepOscillator::new(freq, SR);
+        osc.set_wave(wave, true);
+
+        let power = (0..SR as usize).map(|_| osc.tick(0.0).powi(2)).sum::<f32>() / SR;
+        10.0 * power.log10()
+    }
+
+    #[test]
+    fn level_is_continuous_across_the_crossover() {
+        for wave in [Waveform::Rectangle, Waveform::Sawtooth, Waveform::Triangle] {
+            // from PolyBLEPs only, through the blend, to harmonics only
+            let (start, end) = (ADDITIVE_START * 0.8 * SR, ADDITIVE_FULL * 1.2 * SR);
+            let levels: Vec<f32> = (0..=20)
+                .map(|i| level(wave, start + (end - start) * i as f32 / 20.0))
+                .collect();
+
+            for pair in levels.windows(2) {
+                assert!((pair[1] - pair[0]).abs() < 0.2, "{levels:?}");
+            }
+
+            // only the fundamental is left up there
+            let (high, nyquist) = (level(wave, 20_000.0), level(wave, 23_500.0));
+            assert!((high - nyquist).abs() < 0.1, "{high} dB and {nyquist} dB");
+        }
+    }
+
+    #[test]
+    fn waveforms_stay_within_range() {
+        for wave in [Waveform::Rectangle, Waveform::Sawtooth, Waveform::Triangle] {
+            let mut osc = BlepOscillator::new(1_234.5, SR);
+            osc.set_wave(wave, true);
+
+            assert!((0..SR as usize).all(|_| osc.tick(0.0).abs() <= 1.1));
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Stack of detuned oscillators that are spread across the stereo field.
+
+use crate::{oscillator::BlepOscillator, Waveform};
+use nih_plug::prelude::*;
+
+pub const MAX_UNISON: usize = 8;
+
+/// Position of oscillator `i` out of `count`, evenly spread from -1.0 to 1.0
+fn spread(i: usize, count: usize) -> f32 {
+    if count == 1 {
+        0.0
+    } else {
+        i as f32 / (count - 1) as f32 * 2.0 - 1.0
+    }
+}
+
+#[derive(Enum, PartialEq, Clone, Copy)]
+pub enum StartPhase {
+    /// Every note starts the same way
+    Fixed,
+    Random,
+}
+
+pub struct Unison {
+    oscs: [BlepOscillator; MAX_UNISON],
+    /// Number of oscillators that are playing
+    count: usize,
+    /// Distance of the outermost oscillators from the center, in cents
+    detune: f32,
+    freq: f32,
+    /// Left and right gain per oscillator
+    pans: [(f32, f32); MAX_UNISON],
+    rng_state: u32,
+}
+
+impl Unison {
+    /// Different seeds give different random start phases
+    pub fn new(freq: f32, sr: f32, seed: u32) -> Unison {
+        let mut unison = Unison {
+            oscs: std::array::from_fn(|_| BlepOscillator::new(freq, sr)),
+            count: 1,
+            detune: 0.0,
+            freq,
+            pans: [(1.0, 1.0); MAX_UNISON],
+            // xorshift gets stuck at zero
+            rng_state: seed.max(1),
+        };
+        unison.set_unison(1, 0.0, 1.0);
+
+        unison
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// `width` from 0.0 to 1.0 spreads the oscillators from the center to both sides
+    pub fn set_unison(&mut self, count: usize, detune: f32, width: f32) {
+        self.count = count.clamp(1, MAX_UNISON);
+        self.detune = detune;
+
+        // the overall level stays the same, no matter how many oscillators play
+        let level = 1.0 / (self.count as f32).sqrt();
+
+        for (i, pan) in self.pans.iter_mut().enumerate().take(self.count) {
+            let position = spread(i, self.count) * width;
+            *pan = (
+                (1.0 - position).min(1.0) * level,
+                (1.0 + position).min(1.0) * level,
+            );
+        }
+
+        self.retune();
+    }
+
+    pub fn set_freq(&mut self, freq: f32) {
+        if freq != self.freq {
+            self.freq = freq;
+            self.retune();
+        }
+    }
+
+    pub fn set_wave(&mut self, wave: Waveform, band_limited: bool) {
+        for osc in self.oscs.iter_mut() {
+            osc.set_wave(wave, band_limited);
+        }
+    }
+
+    pub fn restart(&mut self, start_phase: StartPhase) {
+        let phases: [f32; MAX_UNISON] = std::array::from_fn(|_| match start_phase {
+            StartPhase::Fixed => 0.0,
+            StartPhase::Random => self.next_random(),
+        });
+
+        for (osc, phase) in self.oscs.iter_mut().zip(phases) {
+            osc.reset(phase);
+        }
+    }
+
+    /// `offset` shifts the phase of all copies in cycles
+    #[inline(always)]
+    pub fn tick(&mut self, offset: f32) -> (f32, f32) {
+        let mut out = (0.0, 0.0);
+
+        for (osc, (left, right)) in self.oscs.iter_mut().zip(self.pans).take(self.count) {
+            let sample = osc.tick(offset);
+            out.0 += sample * left;
+            out.1 += sample * right;
+        }
+
+        out
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Private Functions
+    ///////////////////////////////////////////////////////////////////////////////
+
+    fn retune(&mut self) {
+        for (i, osc) in self.oscs.iter_mut().enumerate().take(self.count) {
+            let cents = spread(i, self.count) * self.detune;
+            osc.set_freq(self.freq * 2.0_f32.powf(cents / 1200.0));
+        }
+    }
+
+    /// From 0.0 to 1.0
+    fn next_random(&mut self) -> f32 {
+        // xorshift32
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+
+        self.rng_state as f32 / u32::MAX as f32
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    #[test]
+    fn single_oscillator_is_centered() {
+        let mut unison = Unison::new(440.0, SR, 1);
+        unison.set_unison(1, 50.0, 1.0);
+
+        for _ in 0..1_000 {
+            let (left, right) = unison.tick(0.0);
+            assert_eq!(left, right);
+        }
+    }
+
+    #[test]
+    fn width_spreads_the_stack() {
+        let mut unison = Unison::new(440.0, SR, 1);
+
+        unison.set_unison(8, 20.0, 0.0);
+        for _ in 0..1_000 {
+            let (left, right) = unison.tick(0.0);
+            assert_eq!(left, right);
+        }
+
+        unison.set_unison(8, 20.0, 1.0);
+        let difference = (0..1_000)
+            .map(|_| {
+                let (left, right) = unison.tick(0.0);
+                (left - right).abs()
+            })
+            .sum::<f32>();
+        assert!(difference > 1.0, "{difference}");
+    }
+
+    #[test]
+    fn fixed_start_phase_repeats() {
+        let mut unison = Unison::new(440.0, SR, 1);
+        unison.set_unison(4, 30.0, 1.0);
+
+        let mut render = |start_phase| {
+            unison.restart(start_phase);
+            (0..100).map(|_| unison.tick(0.0)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(render(StartPhase::Fixed), render(StartPhase::Fixed));
+        assert_ne!(render(StartPhase::Random), render(StartPhase::Random));
+    }
+}
@@ -0,0 +1,357 @@
+//! Polyphonic voices that are played by MIDI notes.
+
+use crate::{
+    fm::{phase_offset, Operator},
+    unison::{StartPhase, Unison},
+    Waveform,
+};
+use embedded_audio_tools::AudioRateADSR;
+
+pub const MAX_VOICES: usize = 16;
+
+/// Below this, a released voice is considered silent and can be reused
+const SILENCE: f32 = 1e-4;
+
+/// A voice that's still sounding fades out over this time before it plays its next note
+const STEAL_FADE: f32 = 0.002; // seconds
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Held,
+    Released,
+}
+
+struct Voice {
+    osc: Unison,
+    modulator: Operator,
+    adsr: AudioRateADSR,
+    state: State,
+    note: u8,
+    /// Amplitude from the velocity of the note on
+    gain: f32,
+    /// Number of the note on that started this voice, the lowest one is the oldest voice
+    started: u64,
+    /// Goes down from 1.0 while the previous note fades out, `note` starts once it reaches 0.0
+    fade: f32,
+    /// Amplitude of the previous note while it fades out
+    fade_gain: f32,
+}
+
+impl Voice {
+    fn new(sr: f32, seed: u32) -> Voice {
+        Voice {
+            osc: Unison::new(440.0, sr, seed),
+            modulator: Operator::new(sr),
+            adsr: new_envelope(sr),
+            state: State::Idle,
+            note: 0,
+            gain: 0.0,
+            started: 0,
+            fade: 0.0,
+            fade_gain: 0.0,
+        }
+    }
+
+    /// Restarts the oscillators and the envelope for `note`
+    fn start(&mut self, bend: f32, ratio: f32, start_phase: StartPhase) {
+        let freq = note_to_freq(self.note, bend);
+        self.osc.set_freq(freq);
+        self.osc.restart(start_phase);
+        self.modulator.set_freq(freq * ratio);
+        self.modulator.reset();
+        self.adsr.trigger_on();
+
+        // the note off came while the previous note was still fading out
+        if self.state == State::Released {
+            self.adsr.trigger_off();
+        }
+    }
+}
+
+/// Starts from silence, the times are set by `VoicePool::set_envelope()` before every block
+fn new_envelope(sr: f32) -> AudioRateADSR {
+    AudioRateADSR::new(0.01, 0.1, 0.0, 0.1, 0.5, sr)
+}
+
+/// Frequency of a MIDI note, `bend` is in semitones
+fn note_to_freq(note: u8, bend: f32) -> f32 {
+    440.0 * 2.0_f32.powf((note as f32 - 69.0 + bend) / 12.0)
+}
+
+pub struct VoicePool {
+    sr: f32,
+    voices: Vec<Voice>,
+    note_ons: u64,
+    /// Pitch bend in semitones
+    bend: f32,
+    start_phase: StartPhase,
+    /// Frequency of the modulator relative to the note
+    ratio: f32,
+    /// Peak phase deviation in radians
+    index: f32,
+    feedback: f32,
+}
+
+impl VoicePool {
+    /// Allocates all voices, so don't call this on the audio thread
+    pub fn new(sr: f32) -> VoicePool {
+        VoicePool {
+            sr,
+            // every voice gets its own seed, so their random start phases differ
+            voices: (0..MAX_VOICES)
+                .map(|i| Voice::new(sr, i as u32 + 1))
+                .collect(),
+            note_ons: 0,
+            bend: 0.0,
+            start_phase: StartPhase::Fixed,
+            ratio: 1.0,
+            index: 0.0,
+            feedback: 0.0,
+        }
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// All times in seconds, `sustain` from 0.0 to 1.0
+    pub fn set_envelope(&mut self, attack: f32, decay: f32, sustain: f32, release: f32) {
+        for voice in self.voices.iter_mut() {
+            voice.adsr.set_attack(attack);
+            voice.adsr.set_decay(decay);
+            voice.adsr.set_sustain(sustain);
+            voice.adsr.set_release(release);
+        }
+    }
+
+    pub fn set_wave(&mut self, wave: Waveform, band_limited: bool) {
+        for voice in self.voices.iter_mut() {
+            voice.osc.set_wave(wave, band_limited);
+        }
+    }
+
+    /// `detune` in cents, `width` from 0.0 to 1.0, the start phase applies from the next note on
+    pub fn set_unison(&mut self, count: usize, detune: f32, width: f32, start_phase: StartPhase) {
+        self.start_phase = start_phase;
+
+        for voice in self.voices.iter_mut() {
+            voice.osc.set_unison(count, detune, width);
+        }
+    }
+
+    /// `index` is the peak phase deviation in radians, `feedback` from 0.0 to 1.0
+    pub fn set_fm(&mut self, ratio: f32, index: f32, feedback: f32) {
+        self.index = index;
+        self.feedback = feedback;
+
+        if ratio != self.ratio {
+            self.ratio = ratio;
+            self.retune();
+        }
+    }
+
+    /// Retunes all voices, `bend` is in semitones
+    pub fn set_bend(&mut self, bend: f32) {
+        if bend != self.bend {
+            self.bend = bend;
+            self.retune();
+        }
+    }
+
+    /// Only the first `polyphony` voices are used, when they're all busy the oldest one is stolen
+    pub fn note_on(&mut self, note: u8, gain: f32, polyphony: usize) {
+        let voices = &mut self.voices[..polyphony.clamp(1, MAX_VOICES)];
+
+        // the same note again retriggers its voice instead of stacking up
+        let index = voices
+            .iter()
+            .position(|voice| voice.state != State::Idle && voice.note == note)
+            .or_else(|| voices.iter().position(|voice| voice.state == State::Idle))
+            .unwrap_or_else(|| {
+                // released voices are stolen before held ones
+                (0..voices.len())
+                    .min_by_key(|&i| (voices[i].state == State::Held, voices[i].started))
+                    .unwrap()
+            });
+
+        self.note_ons += 1;
+
+        let voice = &mut voices[index];
+
+        // restarting a voice that's still sounding would click, so it fades out first
+        let sounding = voice.state != State::Idle;
+        if sounding && voice.fade == 0.0 {
+            voice.fade = 1.0;
+            voice.fade_gain = voice.gain;
+        }
+
+        voice.state = State::Held;
+        voice.note = note;
+        voice.gain = gain;
+        voice.started = self.note_ons;
+
+        if !sounding {
+            voice.start(self.bend, self.ratio, self.start_phase);
+        }
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        for voice in self.voices.iter_mut() {
+            if voice.state == State::Held && voice.note == note {
+                voice.state = State::Released;
+                voice.adsr.trigger_off();
+            }
+        }
+    }
+
+    /// Silences all voices right away and forgets the pitch bend
+    pub fn all_notes_off(&mut self) {
+        self.bend = 0.0;
+
+        for voice in self.voices.iter_mut() {
+            voice.state = State::Idle;
+            voice.fade = 0.0;
+            // otherwise the next note would start from wherever the envelope was
+            voice.adsr.reset();
+        }
+    }
+
+    /// Sum of all voices, left and right. With an `external` modulator every voice is modulated
+    /// by it instead of its own operator.
+    #[inline(always)]
+    pub fn tick(&mut self, external: Option<f32>) -> (f32, f32) {
+        let mut sum = (0.0, 0.0);
+        let fade_step = 1.0 / (STEAL_FADE * self.sr);
+
+        for voice in self.voices.iter_mut() {
+            if voice.state == State::Idle {
+                continue;
+            }
+
+            let envelope = voice.adsr.tick();
+            let modulator = external.unwrap_or_else(|| voice.modulator.tick(self.feedback));
+            let (left, right) = voice.osc.tick(phase_offset(modulator, self.index));
+
+            let gain = if voice.fade > 0.0 {
+                voice.fade * voice.fade_gain
+            } else {
+                voice.gain
+            };
+            sum.0 += left * envelope * gain;
+            sum.1 += right * envelope * gain;
+
+            if voice.fade > 0.0 {
+                voice.fade -= fade_step;
+
+                if voice.fade <= 0.0 {
+                    voice.fade = 0.0;
+                    voice.start(self.bend, self.ratio, self.start_phase);
+                }
+            } else if voice.state == State::Released && envelope < SILENCE {
+                voice.state = State::Idle;
+            }
+        }
+
+        sum
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Private Functions
+    ///////////////////////////////////////////////////////////////////////////////
+
+    fn retune(&mut self) {
+        // fading voices still play their previous note, they're tuned when they start
+        for voice in self.voices.iter_mut().filter(|voice| voice.fade == 0.0) {
+            let freq = note_to_freq(voice.note, self.bend);
+            voice.osc.set_freq(freq);
+            voice.modulator.set_freq(freq * self.ratio);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(pool: &VoicePool) -> Vec<u8> {
+        pool.voices
+            .iter()
+            .filter(|voice| voice.state != State::Idle)
+            .map(|voice| voice.note)
+            .collect()
+    }
+
+    #[test]
+    fn oldest_voice_is_stolen() {
+        let mut pool = VoicePool::new(48_000.0);
+
+        for note in 60..64 {
+            pool.note_on(note, 1.0, 3);
+        }
+        assert_eq!(playing(&pool), [63, 61, 62]);
+
+        // a released voice goes before the oldest held one
+        pool.note_off(62);
+        pool.note_on(64, 1.0, 3);
+        assert_eq!(playing(&pool), [63, 61, 64]);
+    }
+
+    #[test]
+    fn repeated_note_reuses_its_voice() {
+        let mut pool = VoicePool::new(48_000.0);
+
+        pool.note_on(60, 1.0, 4);
+        pool.note_on(60, 0.5, 4);
+
+        assert_eq!(playing(&pool), [60]);
+    }
+
+    #[test]
+    fn stolen_voice_fades_out_first() {
+        let mut pool = VoicePool::new(48_000.0);
+
+        pool.note_on(60, 1.0, 1);
+        for _ in 0..4_800 {
+            pool.tick(None);
+        }
+
+        pool.note_on(72, 1.0, 1);
+        let fade: Vec<f32> = (0..100).map(|_| pool.tick(None).0.abs()).collect();
+
+        // shortly before the new note starts, the old one is almost gone
+        assert!(fade[94] < 0.03, "{}", fade[94]);
+        assert_eq!(pool.voices[0].fade, 0.0);
+        assert_eq!(playing(&pool), [72]);
+    }
+
+    #[test]
+    fn voices_never_allocate() {
+        let mut pool = VoicePool::new(48_000.0);
+
+        assert_no_alloc::assert_no_alloc(|| {
+            for note in 60..80 {
+                pool.note_on(note, 1.0, MAX_VOICES);
+                pool.tick(None);
+            }
+            pool.note_off(60);
+            pool.all_notes_off();
+            pool.note_on(60, 1.0, MAX_VOICES);
+
+            for _ in 0..1_000 {
+                pool.tick(None);
+            }
+        });
+    }
+
+    #[test]
+    fn note_frequencies() {
+        assert_eq!(note_to_freq(69, 0.0), 440.0);
+        assert!((note_to_freq(57, 0.0) - 220.0).abs() < 1e-3);
+        assert!((note_to_freq(69, 12.0) - 880.0).abs() < 1e-3);
+    }
+}
@@ -1,13 +1,48 @@
 use embedded_audio_tools::stereo::stereo_pan_unchecked;
 use nih_plug::prelude::*;
+use plugin_common::{
+    param::{percentage, seconds},
+    plugin_info, sidechain_sample, stereo_frame,
+    tempo::NoteDivision,
+    transient::{GateEdge, ThresholdGate},
+    STEREO_SIDECHAIN_IO_LAYOUTS,
+};
 use std::sync::{
     atomic::{AtomicBool, Ordering::Relaxed},
     Arc,
 };
 
+mod sequencer;
+
+use sequencer::{GateEvent, Sequencer, Step, MAX_STEPS};
+
+#[derive(Enum, PartialEq)]
+enum Trigger {
+    /// Every note on starts the envelope over
+    Retrigger,
+    /// Only the first of overlapping notes starts the envelope
+    Legato,
+}
+
+/// The signal that opens and closes the envelope when it crosses the threshold
+#[derive(Enum, Clone, Copy, PartialEq)]
+enum AudioTrigger {
+    Off,
+    Sidechain,
+    Input,
+}
+
 struct StereoVCA {
     params: Arc<StereoVCAParams>,
     adsr: embedded_audio_tools::AudioRateADSR,
+    /// One bit per MIDI note that's held down
+    held_notes: u128,
+    /// Peak level from the velocity of the note that started the envelope
+    peak: f32,
+    threshold_gate: ThresholdGate,
+    /// The source the threshold gate listened to in the last buffer
+    audio_trigger: AudioTrigger,
+    sequencer: Sequencer,
 }
 
 #[derive(Params)]
@@ -30,14 +65,102 @@ struct StereoVCAParams {
     #[id = "Gate"]
     pub gate: BoolParam,
 
+    #[id = "Trigger"]
+    pub trigger: EnumParam<Trigger>,
+
+    /// How much the velocity of a note affects the peak level
+    #[id = "Velocity"]
+    pub velocity: FloatParam,
+
+    #[id = "Audio Trigger"]
+    pub audio_trigger: EnumParam<AudioTrigger>,
+
+    /// In dBFS
+    #[id = "Threshold"]
+    pub threshold: FloatParam,
+
+    /// How far the level has to fall below the threshold to close the envelope again
+    #[id = "Hysteresis"]
+    pub hysteresis: FloatParam,
+
+    /// The envelope stays open at least this long after the level falls
+    #[id = "Hold"]
+    pub hold: FloatParam,
+
+    /// Shortest time between two triggers
+    #[id = "Lockout"]
+    pub lockout: FloatParam,
+
+    /// Turns the envelope upside down, so it ducks the signal instead
+    #[id = "Invert"]
+    pub invert: BoolParam,
+
+    /// Plays the pattern while the transport is running
+    #[id = "Sequencer"]
+    pub sequencer: BoolParam,
+
+    /// Number of steps before the pattern repeats
+    #[id = "Steps"]
+    pub steps: IntParam,
+
+    #[id = "Step Division"]
+    pub step_division: EnumParam<NoteDivision>,
+
+    /// Delays every second step by up to half a step
+    #[id = "Swing"]
+    pub swing: FloatParam,
+
+    /// On/off, velocity and length of every step
+    #[nested(array, group = "Step")]
+    pub step_params: [StepParams; MAX_STEPS],
+
     gate_state: AtomicBool,
 }
 
+#[derive(Params)]
+struct StepParams {
+    #[id = "Step On"]
+    pub on: BoolParam,
+
+    #[id = "Step Velocity"]
+    pub velocity: FloatParam,
+
+    /// How long the envelope stays open, relative to the step
+    #[id = "Step Length"]
+    pub length: FloatParam,
+}
+
+impl StepParams {
+    /// `number` counts from 1, the way the steps are displayed
+    fn new(number: usize) -> Self {
+        let step = Step::default();
+
+        Self {
+            on: BoolParam::new(format!("Step {number} On"), step.on),
+            velocity: percentage(&format!("Step {number} Velocity"), step.velocity, 0),
+            length: percentage(&format!("Step {number} Length"), step.length, 0),
+        }
+    }
+
+    fn step(&self) -> Step {
+        Step {
+            on: self.on.value(),
+            velocity: self.velocity.value(),
+            length: self.length.value(),
+        }
+    }
+}
+
 impl Default for StereoVCA {
     fn default() -> Self {
         Self {
             params: Arc::new(StereoVCAParams::default()),
-            adsr: embedded_audio_tools::AudioRateADSR::new(0.01, 0.1, 0.0, 0.1, 0.5, 48_000.0),
+            adsr: new_envelope(48_000.0),
+            held_notes: 0,
+            peak: 1.0,
+            threshold_gate: ThresholdGate::new(48_000.0),
+            audio_trigger: AudioTrigger::Off,
+            sequencer: Sequencer::default(),
         }
     }
 }
@@ -57,63 +180,107 @@ impl Default for StereoVCAParams {
             )
             .with_value_to_string(formatters::v2s_f32_panning()),
 
-            attack: FloatParam::new(
+            attack: seconds(
                 "Attack",
                 0.5,
                 FloatRange::Linear {
                     min: 0.001,
                     max: 3.0,
                 },
-            )
-            .with_value_to_string(formatters::v2s_f32_rounded(3))
-            .with_unit(" s"),
+            ),
 
-            decay: FloatParam::new(
+            decay: seconds(
                 "Decay",
                 0.5,
                 FloatRange::Linear {
                     min: 0.001,
                     max: 5.0,
                 },
-            )
-            .with_value_to_string(formatters::v2s_f32_rounded(3))
-            .with_unit(" s"),
+            ),
 
             sustain: FloatParam::new("Sustain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
                 .with_value_to_string(formatters::v2s_f32_rounded(2)),
 
-            release: FloatParam::new(
+            release: seconds(
                 "Release",
                 0.5,
                 FloatRange::Linear {
                     min: 0.001,
                     max: 5.0,
                 },
-            )
-            .with_value_to_string(formatters::v2s_f32_rounded(3))
-            .with_unit(" s"),
+            ),
 
             gate: BoolParam::new("Gate", false),
+            trigger: EnumParam::new("Trigger", Trigger::Retrigger),
+            velocity: percentage("Velocity", 0.0, 0),
+
+            audio_trigger: EnumParam::new("Audio Trigger", AudioTrigger::Off),
+
+            threshold: FloatParam::new(
+                "Threshold",
+                -20.0,
+                FloatRange::Linear {
+                    min: -60.0,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            hysteresis: FloatParam::new(
+                "Hysteresis",
+                6.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+
+            hold: milliseconds("Hold", 50.0),
+            lockout: milliseconds("Lockout", 20.0),
+
+            invert: BoolParam::new("Invert", false),
+
+            sequencer: BoolParam::new("Sequencer", false),
+            steps: IntParam::new(
+                "Steps",
+                16,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_STEPS as i32,
+                },
+            ),
+            step_division: EnumParam::new("Step Division", NoteDivision::Sixteenth),
+            swing: percentage("Swing", 0.0, 0),
+            step_params: std::array::from_fn(|i| StepParams::new(i + 1)),
             gate_state: AtomicBool::new(false),
         }
     }
 }
 
-impl Plugin for StereoVCA {
-    const NAME: &'static str = "StereoVCA";
-    const VENDOR: &'static str = "Max Genson";
-    const URL: &'static str = "https://www.maxgenson.de";
-    const EMAIL: &'static str = "mail@maxgenson.de";
+/// Time in milliseconds, from 0 to 1 second
+fn milliseconds(name: &str, default: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed {
+            min: 0.0,
+            max: 1_000.0,
+            factor: 0.5,
+        },
+    )
+    .with_unit(" ms")
+    .with_value_to_string(formatters::v2s_f32_rounded(0))
+}
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+impl Plugin for StereoVCA {
+    plugin_info!("StereoVCA");
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
-        ..AudioIOLayout::const_default()
-    }];
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = STEREO_SIDECHAIN_IO_LAYOUTS;
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
     type SysExMessage = ();
@@ -123,11 +290,29 @@ impl Plugin for StereoVCA {
         self.params.clone()
     }
 
+    fn initialize(
+        &mut self,
+        _audio_io_layout: &AudioIOLayout,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.adsr = new_envelope(buffer_config.sample_rate);
+        self.threshold_gate = ThresholdGate::new(buffer_config.sample_rate);
+
+        true
+    }
+
+    fn reset(&mut self) {
+        self.held_notes = 0;
+        self.threshold_gate.reset();
+        self.sequencer.reset();
+    }
+
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
         // update adsr
         self.adsr.set_attack(self.params.attack.smoothed.next());
@@ -141,21 +326,59 @@ impl Plugin for StereoVCA {
 
         if gate_state != current_gate && current_gate {
             self.params.gate_state.store(true, Relaxed);
+            self.peak = 1.0;
             self.adsr.trigger_on();
         } else if gate_state != current_gate && !current_gate {
             self.params.gate_state.store(false, Relaxed);
             self.adsr.trigger_off();
         }
 
+        let audio_trigger = self.params.audio_trigger.value();
+        self.set_audio_trigger(audio_trigger);
+
+        let params = &self.params;
+
+        self.threshold_gate
+            .set_threshold(params.threshold.value(), params.hysteresis.value());
+        self.threshold_gate
+            .set_times(params.hold.value(), params.lockout.value());
+
+        self.sequencer
+            .set_pattern(params.step_params.iter().map(StepParams::step));
+        self.sequencer.set_timing(
+            params.steps.value() as usize,
+            params.step_division.value().beats(),
+            params.swing.value(),
+        );
+
+        let transport = context.transport();
+        let position = song_position(
+            transport.tempo,
+            transport.pos_beats(),
+            transport.playing,
+            transport.sample_rate,
+        )
+        .filter(|_| params.sequencer.value());
+        if position.is_none() && self.sequencer.stop().is_some() {
+            self.adsr.trigger_off();
+        }
+
+        let mut next_event = context.next_event();
+
         // process buffer
-        for channel_samples in buffer.iter_samples() {
-            let envelope_gain = self.adsr.tick();
+        for (n, channel_samples) in buffer.iter_samples().enumerate() {
+            self.handle_events(n, &mut next_event, || context.next_event());
+
+            let (left, right) = stereo_frame(channel_samples);
 
-            let mut samples = channel_samples.into_iter();
-            let (left, right) = (samples.next().unwrap(), samples.next().unwrap());
+            let trigger_input = match audio_trigger {
+                AudioTrigger::Off => None,
+                AudioTrigger::Sidechain => Some(sidechain_sample(aux, n)),
+                AudioTrigger::Input => Some((*left + *right) * 0.5),
+            };
+            let beat = position.map(|(start, increment)| start + n as f64 * increment);
 
-            let panned = stereo_pan_unchecked(self.params.pan.smoothed.next(), (*left, *right));
-            (*left, *right) = (panned.0 * envelope_gain, panned.1 * envelope_gain);
+            (*left, *right) = self.tick((*left, *right), trigger_input, beat);
         }
 
         ProcessStatus::Normal
@@ -164,6 +387,131 @@ impl Plugin for StereoVCA {
     fn deactivate(&mut self) {}
 }
 
+/// Starts closed, the times are set from the parameters before every buffer
+fn new_envelope(sr: f32) -> embedded_audio_tools::AudioRateADSR {
+    embedded_audio_tools::AudioRateADSR::new(0.01, 0.1, 0.0, 0.1, 0.5, sr)
+}
+
+/// Song position at the start of the buffer and beats per sample, while the transport is running
+fn song_position(
+    tempo: Option<f64>,
+    beats: Option<f64>,
+    playing: bool,
+    sr: f32,
+) -> Option<(f64, f64)> {
+    match (tempo, beats) {
+        (Some(tempo), Some(beats)) if playing => Some((beats, tempo / 60.0 / sr as f64)),
+        _ => None,
+    }
+}
+
+impl StereoVCA {
+    /// Processes one stereo frame, `beat` is the song position while the sequencer plays
+    fn tick(
+        &mut self,
+        (left, right): (f32, f32),
+        trigger_input: Option<f32>,
+        beat: Option<f64>,
+    ) -> (f32, f32) {
+        match trigger_input.and_then(|input| self.threshold_gate.tick(input)) {
+            Some(GateEdge::Open) => {
+                self.peak = 1.0;
+                self.adsr.trigger_on();
+            }
+            Some(GateEdge::Close) => self.adsr.trigger_off(),
+            None => (),
+        }
+
+        match beat.and_then(|beat| self.sequencer.tick(beat)) {
+            Some(GateEvent::Open(velocity)) => {
+                self.peak = velocity;
+                self.adsr.trigger_on();
+            }
+            Some(GateEvent::Close) => self.adsr.trigger_off(),
+            None => (),
+        }
+
+        let envelope_gain = self.adsr.tick() * self.peak;
+        let envelope_gain = if self.params.invert.value() {
+            1.0 - envelope_gain
+        } else {
+            envelope_gain
+        };
+
+        let panned = stereo_pan_unchecked(self.params.pan.smoothed.next(), (left, right));
+        (panned.0 * envelope_gain, panned.1 * envelope_gain)
+    }
+
+    /// An open gate would never see the level of its old source fall, so it's closed on a change
+    fn set_audio_trigger(&mut self, audio_trigger: AudioTrigger) {
+        if audio_trigger == self.audio_trigger {
+            return;
+        }
+        self.audio_trigger = audio_trigger;
+
+        if self.threshold_gate.is_open() {
+            self.adsr.trigger_off();
+        }
+        self.threshold_gate.reset();
+    }
+
+    /// Handles all events up to sample `n`, so notes start and end the envelope right at their
+    /// sample. `next_event` is the first one that hasn't been handled yet.
+    fn handle_events(
+        &mut self,
+        n: usize,
+        next_event: &mut Option<NoteEvent<()>>,
+        mut next: impl FnMut() -> Option<NoteEvent<()>>,
+    ) {
+        while let Some(event) = *next_event {
+            if event.timing() > n as u32 {
+                break;
+            }
+
+            match event {
+                NoteEvent::NoteOn { note, velocity, .. } => self.note_on(note, velocity),
+                NoteEvent::NoteOff { note, .. } => self.note_off(note),
+                _ => (),
+            }
+
+            *next_event = next();
+        }
+    }
+
+    fn note_on(&mut self, note: u8, velocity: f32) {
+        let legato = self.params.trigger.value() == Trigger::Legato && self.held_notes != 0;
+        self.held_notes |= 1u128 << note;
+
+        if !legato {
+            let amount = self.params.velocity.value();
+            self.peak = 1.0 - amount + amount * velocity;
+            self.adsr.trigger_on();
+        }
+    }
+
+    /// The envelope is only released with the last note
+    fn note_off(&mut self, note: u8) {
+        let was_held = self.held_notes != 0;
+        self.held_notes &= !(1u128 << note);
+
+        if was_held && self.held_notes == 0 {
+            self.adsr.trigger_off();
+        }
+    }
+}
+
+impl ClapPlugin for StereoVCA {
+    const CLAP_ID: &'static str = "de.maxgenson.stereo-vca";
+    const CLAP_DESCRIPTION: Option<&'static str> = Some("ADSR driven VCA with stereo panning");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Utility,
+    ];
+}
+
 impl Vst3Plugin for StereoVCA {
     const VST3_CLASS_ID: [u8; 16] = *b"StereoVCAMG.....";
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
@@ -173,4 +521,172 @@ impl Vst3Plugin for StereoVCA {
     ];
 }
 
+nih_export_clap!(StereoVCA);
 nih_export_vst3!(StereoVCA);
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changing_the_audio_trigger_closes_the_gate() {
+        let mut vca = StereoVCA::default();
+        vca.set_audio_trigger(AudioTrigger::Sidechain);
+
+        let opened = (0..1_000).any(|_| vca.threshold_gate.tick(1.0) == Some(GateEdge::Open));
+        assert!(opened && vca.threshold_gate.is_open());
+
+        // the level never falls now, so only the change can close it
+        vca.set_audio_trigger(AudioTrigger::Off);
+        assert!(!vca.threshold_gate.is_open());
+    }
+
+    fn vca_with(trigger: Trigger, velocity: f32) -> StereoVCA {
+        let params = StereoVCAParams {
+            trigger: EnumParam::new("Trigger", trigger),
+            velocity: percentage("Velocity", velocity, 0),
+            ..Default::default()
+        };
+        params.pan.smoothed.reset(0.0);
+
+        StereoVCA {
+            params: Arc::new(params),
+            ..Default::default()
+        }
+    }
+
+    /// Left output for a constant input, with the events handled the way `process` does
+    fn render(vca: &mut StereoVCA, events: Vec<NoteEvent<()>>, len: usize) -> Vec<f32> {
+        let mut events = events.into_iter();
+        let mut next_event = events.next();
+
+        (0..len)
+            .map(|n| {
+                vca.handle_events(n, &mut next_event, || events.next());
+                vca.tick((1.0, 1.0), None, None).0
+            })
+            .collect()
+    }
+
+    /// Left output with the envelope started and released by hand right before the given samples
+    fn expected(mut vca: StereoVCA, peak: f32, ons: &[usize], off: usize, len: usize) -> Vec<f32> {
+        vca.peak = peak;
+
+        (0..len)
+            .map(|n| {
+                if ons.contains(&n) {
+                    vca.adsr.trigger_on();
+                }
+                if n == off {
+                    vca.adsr.trigger_off();
+                }
+                vca.tick((1.0, 1.0), None, None).0
+            })
+            .collect()
+    }
+
+    /// Two overlapping notes, the first one is released while the second one is still held
+    fn overlapping_notes() -> Vec<NoteEvent<()>> {
+        let on = |timing, note| NoteEvent::NoteOn {
+            timing,
+            voice_id: None,
+            channel: 0,
+            note,
+            velocity: 0.5,
+        };
+        let off = |timing, note| NoteEvent::NoteOff {
+            timing,
+            voice_id: None,
+            channel: 0,
+            note,
+            velocity: 0.0,
+        };
+
+        vec![on(100, 60), on(200, 64), off(300, 60), off(400, 64)]
+    }
+
+    #[test]
+    fn notes_start_and_release_the_envelope_on_their_sample() {
+        let output = render(
+            &mut vca_with(Trigger::Retrigger, 0.0),
+            overlapping_notes(),
+            1_000,
+        );
+
+        // every note on restarts the envelope, only the last note off releases it
+        let expected = expected(
+            vca_with(Trigger::Retrigger, 0.0),
+            1.0,
+            &[100, 200],
+            400,
+            1_000,
+        );
+
+        assert!(output[..100].iter().all(|&sample| sample == 0.0));
+        assert!(output[100..].iter().any(|&sample| sample > 0.0));
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn overlapping_note_does_not_retrigger_in_legato() {
+        let output = render(
+            &mut vca_with(Trigger::Legato, 1.0),
+            overlapping_notes(),
+            1_000,
+        );
+
+        // the velocity of the first note sets the peak level
+        let expected = expected(vca_with(Trigger::Legato, 1.0), 0.5, &[100], 400, 1_000);
+
+        assert_eq!(output, expected);
+        assert_ne!(
+            output,
+            render(
+                &mut vca_with(Trigger::Retrigger, 1.0),
+                overlapping_notes(),
+                1_000
+            )
+        );
+    }
+
+    #[test]
+    fn step_params_start_with_the_default_pattern() {
+        let params = StereoVCAParams::default();
+        let steps: Vec<Step> = params.step_params.iter().map(StepParams::step).collect();
+
+        assert_eq!(steps, [Step::default(); MAX_STEPS]);
+    }
+
+    #[test]
+    fn sequencer_opens_on_the_sample_of_the_step() {
+        const SR: f32 = 48_000.0;
+        const BLOCK: usize = 256;
+
+        let mut vca = StereoVCA::default();
+        vca.params.pan.smoothed.reset(0.0);
+
+        // 120 BPM from the middle of the first step, which is already closed there. The second
+        // step starts at beat 0.25, an eighth of a beat or 3000 samples later.
+        let mut output = Vec::new();
+        for block in 0..16 {
+            // the host's tempo and position at the start of the block, 24000 samples per beat
+            let pos_beats = 0.125 + (block * BLOCK) as f64 / 24_000.0;
+            let (start, increment) = song_position(Some(120.0), Some(pos_beats), true, SR).unwrap();
+
+            output.extend((0..BLOCK).map(|n| {
+                let beat = start + n as f64 * increment;
+                vca.tick((1.0, 1.0), None, Some(beat)).0
+            }));
+        }
+
+        assert!(output[..3_000].iter().all(|&sample| sample == 0.0));
+        assert!(output[3_001] > 0.0);
+
+        // a stopped transport doesn't move the sequencer
+        assert_eq!(song_position(Some(120.0), Some(0.125), false, SR), None);
+    }
+}
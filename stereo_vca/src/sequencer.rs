@@ -0,0 +1,220 @@
+//! Step sequencer that opens and closes the envelope in time with the host's transport.
+
+pub const MAX_STEPS: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub on: bool,
+    /// Peak level of the envelope, from 0.0 to 1.0
+    pub velocity: f32,
+    /// How long the envelope stays open, from 0.0 to 1.0 of the step
+    pub length: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Step {
+            on: true,
+            velocity: 1.0,
+            length: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GateEvent {
+    /// Open the envelope with this peak level
+    Open(f32),
+    Close,
+}
+
+pub struct Sequencer {
+    steps: [Step; MAX_STEPS],
+    /// Number of steps before the pattern repeats
+    length: usize,
+    /// Length of one step in beats
+    step_beats: f64,
+    /// From 0.0 to 1.0, how far every second step is delayed, up to half a step
+    swing: f64,
+
+    /// Number of the current step, counted from the start of the song
+    current: Option<i64>,
+    open: bool,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Sequencer {
+            steps: [Step::default(); MAX_STEPS],
+            length: 16,
+            step_beats: 0.25,
+            swing: 0.0,
+
+            current: None,
+            open: false,
+        }
+    }
+}
+
+impl Sequencer {
+    ///////////////////////////////////////////////////////////////////////////////
+    // Public Interface
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// All steps, also the ones beyond the pattern length, so they're not lost when it's
+    /// shortened. Steps that are missing from `pattern` stay as they were.
+    pub fn set_pattern(&mut self, pattern: impl IntoIterator<Item = Step>) {
+        for (step, new) in self.steps.iter_mut().zip(pattern) {
+            *step = new;
+        }
+    }
+
+    /// `length` in steps, `step_beats` is the length of a step in quarter notes and `swing` from
+    /// 0.0 to 1.0
+    pub fn set_timing(&mut self, length: usize, step_beats: f32, swing: f32) {
+        self.length = length.clamp(1, MAX_STEPS);
+        self.step_beats = step_beats as f64;
+        self.swing = swing as f64;
+    }
+
+    /// Forgets the current step, so the next tick starts a new one
+    pub fn reset(&mut self) {
+        self.current = None;
+        self.open = false;
+    }
+
+    /// Stops at the current position, closing the envelope if it's open
+    pub fn stop(&mut self) -> Option<GateEvent> {
+        let open = self.open;
+        self.reset();
+
+        open.then_some(GateEvent::Close)
+    }
+
+    /// `beat` is the song position in quarter notes. Returns what the envelope has to do on this
+    /// sample.
+    #[inline(always)]
+    pub fn tick(&mut self, beat: f64) -> Option<GateEvent> {
+        let (number, fraction) = self.locate(beat);
+        let step = self.steps[number.rem_euclid(self.length as i64) as usize];
+        let gate = step.on && fraction < step.length as f64;
+
+        if self.current != Some(number) {
+            self.current = Some(number);
+
+            if gate {
+                self.open = true;
+                return Some(GateEvent::Open(step.velocity));
+            }
+        }
+
+        if self.open && !gate {
+            self.open = false;
+            return Some(GateEvent::Close);
+        }
+
+        None
+    }
+
+    ///////////////////////////////////////////////////////////////////////////////
+    // Private Functions
+    ///////////////////////////////////////////////////////////////////////////////
+
+    /// Number of the step at `beat` and how far into it that is, from 0.0 to 1.0. Swing moves
+    /// the start of every odd step back, which makes the even steps longer and the odd ones
+    /// shorter.
+    fn locate(&self, beat: f64) -> (i64, f64) {
+        let step = self.step_beats;
+        let pair = (beat / (2.0 * step)).floor();
+        let within = beat - pair * 2.0 * step;
+
+        let even = step * (1.0 + self.swing * 0.5);
+        let (second, into, length) = if within < even {
+            (0, within, even)
+        } else {
+            (1, within - even, 2.0 * step - even)
+        };
+
+        (pair as i64 * 2 + second, (into / length).clamp(0.0, 1.0))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+///////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Events with their position in beats, at 100 ticks per beat
+    fn events(sequencer: &mut Sequencer, beats: usize) -> Vec<(usize, GateEvent)> {
+        (0..beats * 100)
+            .filter_map(|n| sequencer.tick(n as f64 / 100.0).map(|event| (n, event)))
+            .collect()
+    }
+
+    #[test]
+    fn steps_open_and_close_in_time() {
+        let mut sequencer = Sequencer::default();
+        sequencer.set_timing(4, 0.25, 0.0);
+
+        let mut pattern = [Step::default(); MAX_STEPS];
+        pattern[1].on = false;
+        pattern[2].velocity = 0.5;
+        pattern[3].length = 1.0;
+        sequencer.set_pattern(pattern);
+
+        assert_eq!(
+            events(&mut sequencer, 1),
+            [
+                (0, GateEvent::Open(1.0)),
+                (13, GateEvent::Close),
+                (50, GateEvent::Open(0.5)),
+                (63, GateEvent::Close),
+                (75, GateEvent::Open(1.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn pattern_repeats_after_its_length() {
+        let mut sequencer = Sequencer::default();
+        sequencer.set_timing(3, 0.25, 0.0);
+
+        let mut pattern = [Step::default(); MAX_STEPS];
+        pattern[0].velocity = 0.25;
+        sequencer.set_pattern(pattern);
+
+        let accents: Vec<usize> = events(&mut sequencer, 2)
+            .into_iter()
+            .filter(|(_, event)| *event == GateEvent::Open(0.25))
+            .map(|(n, _)| n)
+            .collect();
+
+        assert_eq!(accents, [0, 75, 150]);
+    }
+
+    #[test]
+    fn swing_delays_the_odd_steps() {
+        let mut sequencer = Sequencer::default();
+        sequencer.set_timing(16, 0.5, 0.5);
+
+        let opened: Vec<usize> = events(&mut sequencer, 2)
+            .into_iter()
+            .filter(|(_, event)| matches!(event, GateEvent::Open(_)))
+            .map(|(n, _)| n)
+            .collect();
+
+        assert_eq!(opened, [0, 63, 100, 163]);
+    }
+
+    #[test]
+    fn stopping_closes_the_gate() {
+        let mut sequencer = Sequencer::default();
+
+        assert_eq!(sequencer.tick(0.0), Some(GateEvent::Open(1.0)));
+        assert_eq!(sequencer.stop(), Some(GateEvent::Close));
+        assert_eq!(sequencer.stop(), None);
+    }
+}